@@ -202,6 +202,107 @@ vec4 point_point(vec4 in_vec, vec4 start, vec4 end)
   return vec4(rotate_by_quat(in_vec.xyz, rotation), dot(dist_vec, dist_vec));
 }
 
+/**
+ * Rotate the input vector about center by the rotation between start and end
+ * as seen from center. Returns null if given null.
+ */
+vec4 point_point_centered(vec4 in_vec, vec4 start, vec4 end, vec4 center)
+{
+  if (in_vec.w < -0.5 || start.w < -0.5 || end.w < -0.5 || center.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec4 rotation = get_rotation_quat(start.xyz - center.xyz, end.xyz - center.xyz);
+  vec3 dist_vec = start.xyz - in_vec.xyz;
+  return vec4(center.xyz + rotate_by_quat(in_vec.xyz - center.xyz, rotation), dot(dist_vec, dist_vec));
+}
+
+/**
+ * Shift the input vector by the vector from start to end. Returns null if
+ * given null.
+ */
+vec4 point_translate(vec4 in_vec, vec4 start, vec4 end)
+{
+  if (in_vec.w < -0.5 || start.w < -0.5 || end.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec3 dist_vec = start.xyz - in_vec.xyz;
+  return vec4(in_vec.xyz + end.xyz - start.xyz, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Pull the input vector towards center, or push it away for a negative
+ * strength, by strength times a falloff which is 1 at center and 0 beyond
+ * radius. The falloff is:
+ *   0: linear
+ *   1: smooth
+ *   2: constant
+ * Returns null if given null.
+ */
+vec4 density_shift(vec4 in_vec, vec4 center, float strength, float radius, int falloff)
+{
+  if (in_vec.w < -0.5 || center.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec3 dist_vec = center.xyz - in_vec.xyz;
+  float dist = length(dist_vec);
+  float amount = 0.0;
+  if (radius > Epsilon && dist < radius)
+  {
+    if (falloff == 0)
+      amount = 1.0 - dist / radius;
+    else if (falloff == 1)
+      amount = 1.0 - smoothstep(0.0, radius, dist);
+    else
+      amount = 1.0;
+  }
+  vec3 shifted = in_vec.xyz + strength * amount * dist_vec;
+  if (dot(shifted, shifted) > 1.0)
+  {
+    shifted /= length(shifted);
+  }
+  return vec4(shifted, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Reflect the input vector across the plane through point with the given
+ * normal. The mode is:
+ *   0: fold, only reflecting vectors on the side the normal points to
+ *   1: mirror, reflecting every vector
+ * Returns null if given null or a zero normal.
+ */
+vec4 point_reflect(vec4 in_vec, vec4 point, vec4 normal, int mode)
+{
+  if (in_vec.w < -0.5 || point.w < -0.5 || normal.w < -0.5 || length(normal.xyz) < Epsilon)
+    return vec4(0, 0, 0, -1);
+
+  vec3 unit_normal = normalize(normal.xyz);
+  vec3 dist_vec = point.xyz - in_vec.xyz;
+  float side = dot(in_vec.xyz - point.xyz, unit_normal);
+  vec3 reflected = in_vec.xyz;
+  if (mode != 0 || side > 0.0)
+  {
+    reflected -= 2.0 * side * unit_normal;
+  }
+  if (dot(reflected, reflected) > 1.0)
+  {
+    reflected /= length(reflected);
+  }
+  return vec4(reflected, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Rotate the input vector about the grey axis by angle turns, shifting its
+ * hue by angle. Returns null if given null.
+ */
+vec4 point_twist(vec4 in_vec, float angle)
+{
+  if (in_vec.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec4 rotation = get_axis_angle_quat(vec3(0, 0, 1), angle * 2.0 * M_PI);
+  return vec4(rotate_by_quat(in_vec.xyz, rotation), 0.0);
+}
+
 // === Scaling
 // ===== All functions return (scaled vector with norm <= 1, weight)
 
@@ -239,6 +340,93 @@ vec4 RatioClamp(vec4 in_vec, float start, float end)
   }
 }
 
+/**
+ * RatioClamp for start points close to black, where the ratio between the
+ * lengths is unstable and a black start point leaves a hole. As the start
+ * length falls below knee the result blends smoothly towards a dark length
+ * which depends on dark:
+ *   0: keep the length of in_vec
+ *   1: interpolate the length through (start, end) and (1, 1)
+ *   2: raise the length to at least floor_length
+ */
+vec4 RatioBlend(vec4 in_vec, float start, float end, float knee, int dark, float floor_length)
+{
+  vec4 ratio = RatioClamp(in_vec, max(start, Epsilon), end);
+  float in_len = length(in_vec.xyz);
+  float dark_len;
+  if (dark == 0)
+  {
+    dark_len = in_len;
+  }
+  else if (dark == 1)
+  {
+    dark_len = mix(end, 1.0, (in_len - start) / max(1.0 - start, Epsilon));
+  }
+  else
+  {
+    dark_len = max(in_len, floor_length);
+  }
+  dark_len = clamp(dark_len, 0.0, 1.0);
+
+  // Black has no direction of its own, so it is lifted along the grey axis.
+  vec3 direction = in_len < Epsilon ? vec3(0, 0, 1) : in_vec.xyz / in_len;
+  float weight = knee < Epsilon ? 1.0 : smoothstep(0.0, knee, start);
+  return vec4(mix(direction * dark_len, ratio.xyz, weight), in_vec.w);
+}
+
+/**
+ * Roll lengths above knee off smoothly towards 1 instead of clipping them.
+ * Lengths up to knee are kept and the slope is continuous at knee.
+ */
+float soft_knee(float len, float knee)
+{
+  float headroom = 1.0 - knee;
+  if (len <= knee) return len;
+  if (headroom < Epsilon) return 1.0;
+  float over = len - knee;
+  return knee + over / (1.0 + over / headroom);
+}
+
+/**
+ * Gamut compression of value only. Scales by the ratio between end and start
+ * when ratio is set, then rolls the length off above knee, which keeps hue
+ * and saturation.
+ */
+vec4 SoftValue(vec4 in_vec, float start, float end, float knee, bool ratio)
+{
+  vec3 position = in_vec.xyz;
+  if (ratio)
+  {
+    position *= start < Epsilon ? 0.0 : end / start;
+  }
+  float len = length(position);
+  if (len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+  return vec4(position * soft_knee(len, knee) / len, in_vec.w);
+}
+
+/**
+ * SoftValue which also shrinks the saturation angle by the same proportion
+ * as the length is rolled off, so over-bright colors fade towards white.
+ */
+vec4 SoftSaturation(vec4 in_vec, float start, float end, float knee, bool ratio)
+{
+  vec3 position = in_vec.xyz;
+  if (ratio)
+  {
+    position *= start < Epsilon ? 0.0 : end / start;
+  }
+  float len = length(position);
+  if (len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+  float new_len = soft_knee(len, knee);
+  if (length(position.xy) < Epsilon) return vec4(position * new_len / len, in_vec.w);
+
+  float hue_angle = atan(position.y, position.x);
+  float sat_angle = atan(length(position.xy), abs(position.z)) * new_len / len;
+  float z = position.z < 0.0 ? -cos(sat_angle) : cos(sat_angle);
+  vec3 direction = vec3(sin(sat_angle) * vec2(cos(hue_angle), sin(hue_angle)), z);
+  return vec4(new_len * direction, in_vec.w);
+}
+
 /**
  * Scale the in_vec by the bezier curve given by the controls
  *        (0, 0), (start_mid, end_mid), (1, 1).
@@ -256,3 +444,44 @@ vec4 BezierLoose(vec4 in_vec, float start_mid, float end_mid)
                          mix(end_mid, 1.0, percent), percent);
   return vec4(new_length / in_len * in_vec.xyz, in_vec.w);
 }
+
+/**
+ * Scale the in_vec by a monotone curve through (0, 0), (start_mid, end_mid)
+ * and (1, 1), made of two quadratic bezier segments which meet at
+ * (start_mid, end_mid) with a shared tangent. Unlike BezierLoose a vector of
+ * length start_mid is always mapped to a vector of length end_mid.
+ */
+vec4 BezierStrict(vec4 in_vec, float start_mid, float end_mid)
+{
+  float in_len = length(in_vec.xyz);
+  if (in_len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+
+  float s = clamp(start_mid, 0.0, 1.0);
+  float e = clamp(end_mid, 0.0, 1.0);
+  float x = min(in_len, 1.0);
+
+  // The harmonic mean of the slopes either side of the midpoint keeps the
+  // controls of both segments inside their bounds, so the curve is monotone.
+  float denominator = e * (1.0 - s) + s * (1.0 - e);
+  float slope = denominator < Epsilon ? 1.0 : 2.0 * e * (1.0 - e) / denominator;
+
+  // The controls sit halfway along each segment in x, so x is linear in t.
+  float new_length;
+  if (x <= s)
+  {
+    float t = x / s;
+    float control = e - slope * s / 2.0;
+    new_length = mix(mix(0.0, control, t), mix(control, e, t), t);
+  }
+  else if (s > 1.0 - Epsilon)
+  {
+    new_length = 1.0;
+  }
+  else
+  {
+    float t = (x - s) / (1.0 - s);
+    float control = e + slope * (1.0 - s) / 2.0;
+    new_length = mix(mix(e, control, t), mix(control, 1.0, t), t);
+  }
+  return vec4(new_length / in_len * in_vec.xyz, in_vec.w);
+}
@@ -1,11 +1,20 @@
+#![allow(
+    clippy::if_same_then_else,
+    clippy::legacy_numeric_constants,
+    clippy::match_ref_pats,
+)]
+
 use std::f32::consts::FRAC_PI_2;
 use std::f32::consts::PI;
 use std::f32::EPSILON;
 
+use std::str::FromStr;
+
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Clone)]
-pub struct Color(pub u8, pub u8, pub u8);
+/// An 8 bit color with a straight, not premultiplied, alpha as its last channel.
+#[derive(Clone, Debug)]
+pub struct Color(pub u8, pub u8, pub u8, pub u8);
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum ColorProperties {
@@ -15,6 +24,7 @@ pub enum ColorProperties {
     Red,
     Green,
     Blue,
+    Alpha,
 }
 
 impl ColorProperties {
@@ -27,6 +37,7 @@ impl ColorProperties {
             &Red => "rgb.x",
             &Green => "rgb.y",
             &Blue => "rgb.z",
+            &Alpha => "a",
         }
     }
 
@@ -39,6 +50,7 @@ impl ColorProperties {
             &Red => "rgb",
             &Green => "rgb",
             &Blue => "rgb",
+            &Alpha => "alpha",
         }
     }
 }
@@ -81,7 +93,7 @@ fn dot(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
 
 impl Color {
     pub fn to_hsv_vec(&self) -> (f32, f32, f32) {
-        let &Color(r, g, b) = self;
+        let &Color(r, g, b, _) = self;
         let r = (r as f32) / 255.0;
         let g = (g as f32) / 255.0;
         let b = (b as f32) / 255.0;
@@ -163,6 +175,25 @@ impl Color {
     }
 }
 
+/// Parse colors written in hex as #RRGGBB, or #RRGGBBAA to give an alpha. The leading # is
+/// optional.
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim_start_matches('#');
+        if (hex.len() != 6 && hex.len() != 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "Expected a color of the form #RRGGBB or #RRGGBBAA but found {}",
+                s
+            ));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        let alpha = if hex.len() == 8 { channel(6) } else { 255 };
+        Ok(Color(channel(0), channel(2), channel(4), alpha))
+    }
+}
+
 // ================================================================================================
 // == Serde Serialization for parsing input files.                                               ==
 // ================================================================================================
@@ -171,6 +202,16 @@ struct SerializableColor {
     red: u8,
     green: u8,
     blue: u8,
+    #[serde(default = "opaque", skip_serializing_if = "is_opaque")]
+    alpha: u8,
+}
+
+fn opaque() -> u8 {
+    255
+}
+
+fn is_opaque(alpha: &u8) -> bool {
+    *alpha == 255
 }
 
 impl Serialize for Color {
@@ -182,6 +223,7 @@ impl Serialize for Color {
             red: self.0,
             green: self.1,
             blue: self.2,
+            alpha: self.3,
         }
         .serialize(serializer)
     }
@@ -193,6 +235,13 @@ impl<'de> Deserialize<'de> for Color {
         D: Deserializer<'de>,
     {
         Deserialize::deserialize(deserializer)
-            .map(|SerializableColor { red, green, blue }| Color(red, green, blue))
+            .map(
+                |SerializableColor {
+                     red,
+                     green,
+                     blue,
+                     alpha,
+                 }| Color(red, green, blue, alpha),
+            )
     }
 }
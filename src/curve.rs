@@ -0,0 +1,296 @@
+//! # Curves
+//! User defined curves given by control points, such as scaling curves mapping input lengths to
+//! output lengths or twist curves mapping a channel to an angle. A curve is split into one cubic
+//! polynomial per pair of control points, which is evaluated the same way on the cpu and in the
+//! function generated for it in the shader.
+
+use evaluation::{Vec3, Vec4, EPSILON};
+use validation::Validation;
+
+/// How lengths between two control points are found.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// A cubic spline which never overshoots the control points, so a curve whose control points
+    /// only rise never falls.
+    MonotoneCubic,
+}
+
+/// The part of a curve between two control points. The output length at an input length x is
+/// `a + t * (b + t * (c + t * d))` where t is how far x is between start and end.
+pub struct Segment {
+    start: f32,
+    end: f32,
+    coefficients: [f32; 4],
+}
+
+impl Segment {
+    fn evaluate(&self, x: f32) -> f32 {
+        let [a, b, c, d] = self.coefficients;
+        let t = (x - self.start) / (self.end - self.start);
+        a + t * (b + t * (c + t * d))
+    }
+}
+
+/// Report control points which do not describe a curve. Inputs must be between 0 and 1, and so
+/// must outputs if they are lengths.
+pub fn validate(points: &[(f32, f32)], lengths: bool, path: &str, validation: &mut Validation) {
+    if points.len() < 2 {
+        validation.error(path, "A curve needs at least two control points");
+    }
+    for (i, &(x, y)) in points.iter().enumerate() {
+        let point_path = format!("{}[{}]", path, i);
+        if lengths && (!(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y)) {
+            validation.error(&point_path, "Control points must be lengths between 0 and 1");
+        } else if !(0.0..=1.0).contains(&x) {
+            validation.error(&point_path, "Control points must have inputs between 0 and 1");
+        }
+        if i > 0 && x <= points[i - 1].0 {
+            validation.error(
+                &point_path,
+                "Control points must be in order of strictly increasing input length",
+            );
+        }
+    }
+}
+
+/// Split a curve into one segment per pair of control points.
+pub fn segments(points: &[(f32, f32)], interpolation: &Interpolation) -> Vec<Segment> {
+    let tangents = match interpolation {
+        Interpolation::Linear => Vec::new(),
+        Interpolation::MonotoneCubic => monotone_tangents(points),
+    };
+    points
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let coefficients = match interpolation {
+                Interpolation::Linear => [y0, y1 - y0, 0.0, 0.0],
+                Interpolation::MonotoneCubic => {
+                    // Hermite form with the tangents scaled to the width of the segment.
+                    let d0 = tangents[i] * (x1 - x0);
+                    let d1 = tangents[i + 1] * (x1 - x0);
+                    [
+                        y0,
+                        d0,
+                        3.0 * (y1 - y0) - 2.0 * d0 - d1,
+                        2.0 * (y0 - y1) + d0 + d1,
+                    ]
+                }
+            };
+            Segment {
+                start: x0,
+                end: x1,
+                coefficients,
+            }
+        })
+        .collect()
+}
+
+/// Tangents at every control point which keep a cubic hermite spline monotone between each pair
+/// of them, using the weighted harmonic mean of the neighbouring slopes (Fritsch and Butland).
+fn monotone_tangents(points: &[(f32, f32)]) -> Vec<f32> {
+    let widths: Vec<f32> = points.windows(2).map(|x| x[1].0 - x[0].0).collect();
+    let slopes: Vec<f32> = points
+        .windows(2)
+        .zip(&widths)
+        .map(|(x, width)| (x[1].1 - x[0].1) / width)
+        .collect();
+
+    let mut tangents = Vec::with_capacity(points.len());
+    tangents.push(slopes[0]);
+    for i in 1..slopes.len() {
+        let (before, after) = (slopes[i - 1], slopes[i]);
+        if before * after <= 0.0 {
+            tangents.push(0.0);
+        } else {
+            let (h0, h1) = (widths[i - 1], widths[i]);
+            tangents.push(3.0 * (h0 + h1) / ((2.0 * h1 + h0) / before + (h1 + 2.0 * h0) / after));
+        }
+    }
+    tangents.push(slopes[slopes.len() - 1]);
+    tangents
+}
+
+/// The output of the curve at x. Inputs outside of the control points keep the output of the
+/// closest one.
+pub fn value(segments: &[Segment], x: f32) -> f32 {
+    match segments.iter().position(|segment| x <= segment.end) {
+        Some(0) if x <= segments[0].start => segments[0].coefficients[0],
+        Some(i) => segments[i].evaluate(x),
+        None => segments.last().map_or(0.0, |segment| segment.evaluate(segment.end)),
+    }
+}
+
+/// Scale the in_vec to the length the curve maps its length to.
+pub fn evaluate(segments: &[Segment], in_vec: Vec4) -> Vec4 {
+    let in_len = in_vec.xyz().length();
+    let new_length = value(segments, in_len);
+    // Black has no direction of its own, so it is lifted along the grey axis.
+    let direction = if in_len < EPSILON {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        in_vec.xyz() / in_len
+    };
+    (direction * new_length.clamp(0.0, 1.0)).extend(in_vec.w)
+}
+
+/// The definition of a scaling function for the curve, taking the same arguments as the scaling
+/// functions of the shader library.
+pub fn get_shader(function: &str, path: &str, segments: &[Segment]) -> String {
+    format!(
+        "
+// {path}
+vec4 {function}(vec4 in_vec, float start, float end)
+{{
+  float in_len = length(in_vec.xyz);
+  float new_length = 0.0;{body}
+  vec3 direction = in_len < Epsilon ? vec3(0, 0, 1) : in_vec.xyz / in_len;
+  return vec4(direction * clamp(new_length, 0.0, 1.0), in_vec.w);
+}}
+",
+        path = path,
+        function = function,
+        body = piecewise_shader(segments, "in_len", "new_length")
+    )
+}
+
+/// The definition of a function returning the output of the curve at x, mirroring value.
+pub fn get_value_shader(function: &str, path: &str, segments: &[Segment]) -> String {
+    format!(
+        "
+// {path}
+float {function}(float x)
+{{
+  float y = 0.0;{body}
+  return y;
+}}
+",
+        path = path,
+        function = function,
+        body = piecewise_shader(segments, "x", "y")
+    )
+}
+
+/// Statements setting output to the output of the curve at input.
+fn piecewise_shader(segments: &[Segment], input: &str, output: &str) -> String {
+    let mut body = String::new();
+    if let (Some(first), Some(last)) = (segments.first(), segments.last()) {
+        body += &format!(
+            "
+  if ({input} <= {start:?})
+  {{
+    {output} = {value:?};
+  }}",
+            input = input,
+            output = output,
+            start = first.start,
+            value = first.coefficients[0]
+        );
+        for segment in segments {
+            let [a, b, c, d] = segment.coefficients;
+            body += &format!(
+                "
+  else if ({input} <= {end:?})
+  {{
+    float t = ({input} - {start:?}) / {width:?};
+    {output} = {a:?} + t * ({b:?} + t * ({c:?} + t * {d:?}));
+  }}",
+                input = input,
+                output = output,
+                start = segment.start,
+                end = segment.end,
+                width = segment.end - segment.start,
+                a = a,
+                b = b,
+                c = c,
+                d = d
+            );
+        }
+        body += &format!(
+            "
+  else
+  {{
+    {output} = {value:?};
+  }}",
+            output = output,
+            value = last.evaluate(last.end)
+        );
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERPOLATIONS: [Interpolation; 2] =
+        [Interpolation::Linear, Interpolation::MonotoneCubic];
+
+    fn assert_value(segments: &[Segment], x: f32, expected: f32) {
+        let actual = value(segments, x);
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} maps to {} instead of {}",
+            x,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn passes_through_control_points() {
+        let points = [(0.0, 0.0), (0.3, 0.6), (0.7, 0.65), (0.9, 0.2), (1.0, 1.0)];
+        for interpolation in INTERPOLATIONS.iter() {
+            let segments = segments(&points, interpolation);
+            for &(x, y) in points.iter() {
+                assert_value(&segments, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn monotone_input_stays_monotone() {
+        let points = [(0.0, 0.0), (0.1, 0.5), (0.2, 0.52), (0.8, 0.55), (1.0, 1.0)];
+        let segments = segments(&points, &Interpolation::MonotoneCubic);
+        let mut previous = value(&segments, 0.0);
+        for i in 1..=1000 {
+            let x = i as f32 / 1000.0;
+            let y = value(&segments, x);
+            assert!(y >= previous - 1e-6, "curve falls at {}", x);
+            previous = y;
+        }
+    }
+
+    #[test]
+    fn flat_segments_stay_flat() {
+        let points = [(0.0, 0.2), (0.4, 0.5), (0.6, 0.5), (1.0, 0.9)];
+        for interpolation in INTERPOLATIONS.iter() {
+            let segments = segments(&points, interpolation);
+            for i in 0..=10 {
+                assert_value(&segments, 0.4 + i as f32 * 0.02, 0.5);
+            }
+        }
+
+        let flat = segments(&[(0.0, 0.7), (1.0, 0.7)], &Interpolation::MonotoneCubic);
+        for i in 0..=10 {
+            assert_value(&flat, i as f32 / 10.0, 0.7);
+        }
+    }
+
+    #[test]
+    fn two_points_are_a_line() {
+        let points = [(0.2, 0.3), (0.8, 0.9)];
+        for interpolation in INTERPOLATIONS.iter() {
+            let segments = segments(&points, interpolation);
+            assert_eq!(segments.len(), 1);
+            assert_value(&segments, 0.5, 0.6);
+            assert_value(&segments, 0.65, 0.75);
+            // Inputs outside of the control points keep the output of the closest one.
+            assert_value(&segments, 0.0, 0.3);
+            assert_value(&segments, 1.0, 0.9);
+        }
+    }
+}
@@ -1,11 +1,34 @@
-use crate::color::ColorProperties;
+#![allow(
+    clippy::match_ref_pats,
+    clippy::needless_borrowed_reference,
+)]
+
+use crate::color::{Color, ColorProperties};
 use crate::dependency::DataDependencyGraph;
+use crate::evaluation::{
+    hsv2half_spherical, hsv2rgb, rgb2hsv, Pixel, Sample, Vec3, Vec4, EPSILON,
+};
+use crate::glsl::mangle;
+use crate::validation::Validation;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum DataSourceKind {
     Color,
     Float,
     Image,
+    /// The output of an earlier stage of the folding machine, read like an image.
+    Stage,
+}
+
+impl std::fmt::Display for DataSourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataSourceKind::Color => write!(f, "color"),
+            DataSourceKind::Float => write!(f, "float"),
+            DataSourceKind::Image => write!(f, "image"),
+            DataSourceKind::Stage => write!(f, "stage"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
@@ -27,8 +50,8 @@ pub enum FloatData {
 impl FloatData {
     pub fn reference_string(&self) -> String {
         match self {
-            &Self::Constant(ref x) => format!("{}", x),
-            &Self::FloatRef(ref source) => format!("float_{}", source),
+            &Self::Constant(ref x) => format!("{:?}", x),
+            &Self::FloatRef(ref source) => format!("float_{}", mangle(source)),
             &Self::ColorChannel {
                 ref color_source,
                 ref channel,
@@ -36,6 +59,17 @@ impl FloatData {
         }
     }
 
+    pub fn evaluate(&self, pixel: &Pixel) -> f32 {
+        match self {
+            Self::Constant(x) => *x,
+            Self::FloatRef(source) => pixel.float(source),
+            Self::ColorChannel {
+                color_source,
+                channel,
+            } => color_source.evaluate_channel(pixel, *channel),
+        }
+    }
+
     pub fn get_required_sources(&self, graph: &mut DataDependencyGraph) {
         match self {
             &Self::ColorChannel {
@@ -46,6 +80,12 @@ impl FloatData {
             &Self::FloatRef(ref name) => graph.require_float(name.clone()),
         }
     }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        if let Self::ColorChannel { color_source, .. } = self {
+            color_source.validate(&format!("{}.color_source", path), validation)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -92,8 +132,8 @@ impl ColorData {
                 green.reference_string(),
                 blue.reference_string()
             ),
-            &ColorRef { ref color_source } => format!("col_{}_rgb", color_source),
-            &ImageRef { ref image_source } => format!("img_{}_rgb", image_source),
+            &ColorRef { ref color_source } => format!("col_{}__rgb", mangle(color_source)),
+            &ImageRef { ref image_source } => format!("img_{}__rgb", mangle(image_source)),
         }
     }
 
@@ -120,8 +160,8 @@ impl ColorData {
                 green.reference_string(),
                 blue.reference_string()
             ),
-            &ColorRef { ref color_source } => format!("col_{}_hsv", color_source),
-            &ImageRef { ref image_source } => format!("img_{}_hsv", image_source),
+            &ColorRef { ref color_source } => format!("col_{}__hsv", mangle(color_source)),
+            &ImageRef { ref image_source } => format!("img_{}__hsv", mangle(image_source)),
         }
     }
 
@@ -135,6 +175,9 @@ impl ColorData {
             (&RGBColor { ref red, .. }, Red) => red.reference_string(),
             (&RGBColor { ref green, .. }, Green) => green.reference_string(),
             (&RGBColor { ref blue, .. }, Blue) => blue.reference_string(),
+            (&HSVColor { .. }, Alpha) | (&RGBColor { .. }, Alpha) => String::from("1.0"),
+            (&ColorRef { ref color_source }, Alpha) => format!("col_{}__a", mangle(color_source)),
+            (&ImageRef { ref image_source }, Alpha) => format!("img_{}__a", mangle(image_source)),
             (x, Hue) => format!("{}.x", x.hsv_vec()),
             (x, Saturation) => format!("{}.y", x.hsv_vec()),
             (x, Value) => format!("{}.z", x.hsv_vec()),
@@ -167,8 +210,83 @@ impl ColorData {
                 green.reference_string(),
                 blue.reference_string()
             ),
-            &ColorRef { ref color_source } => format!("col_{}", color_source),
-            &ImageRef { ref image_source } => format!("img_{}", image_source),
+            &ColorRef { ref color_source } => format!("col_{}", mangle(color_source)),
+            &ImageRef { ref image_source } => format!("img_{}", mangle(image_source)),
+        }
+    }
+
+    pub fn evaluate_rgb(&self, pixel: &Pixel) -> Vec3 {
+        use self::ColorData::*;
+        match self {
+            HSVColor {
+                hue,
+                saturation,
+                value,
+            } => hsv2rgb(Vec3::new(
+                hue.evaluate(pixel),
+                saturation.evaluate(pixel),
+                value.evaluate(pixel),
+            )),
+            RGBColor { red, green, blue } => Vec3::new(
+                red.evaluate(pixel),
+                green.evaluate(pixel),
+                blue.evaluate(pixel),
+            ),
+            ColorRef { color_source } => pixel.color(color_source).rgb,
+            ImageRef { image_source } => pixel.image(image_source).rgb,
+        }
+    }
+
+    pub fn evaluate_hsv(&self, pixel: &Pixel) -> Vec3 {
+        use self::ColorData::*;
+        match self {
+            HSVColor {
+                hue,
+                saturation,
+                value,
+            } => Vec3::new(
+                hue.evaluate(pixel),
+                saturation.evaluate(pixel),
+                value.evaluate(pixel),
+            ),
+            RGBColor { red, green, blue } => rgb2hsv(Vec3::new(
+                red.evaluate(pixel),
+                green.evaluate(pixel),
+                blue.evaluate(pixel),
+            )),
+            ColorRef { color_source } => pixel.color(color_source).hsv,
+            ImageRef { image_source } => pixel.image(image_source).hsv,
+        }
+    }
+
+    pub fn evaluate_channel(&self, pixel: &Pixel, channel: ColorProperties) -> f32 {
+        use self::ColorData::*;
+        use crate::color::ColorProperties::*;
+        match (self, channel) {
+            (HSVColor { hue, .. }, Hue) => hue.evaluate(pixel),
+            (HSVColor { saturation, .. }, Saturation) => saturation.evaluate(pixel),
+            (HSVColor { value, .. }, Value) => value.evaluate(pixel),
+            (RGBColor { red, .. }, Red) => red.evaluate(pixel),
+            (RGBColor { green, .. }, Green) => green.evaluate(pixel),
+            (RGBColor { blue, .. }, Blue) => blue.evaluate(pixel),
+            (HSVColor { .. }, Alpha) | (RGBColor { .. }, Alpha) => 1.0,
+            (ColorRef { color_source }, Alpha) => pixel.color(color_source).alpha,
+            (ImageRef { image_source }, Alpha) => pixel.image(image_source).alpha,
+            (x, Hue) => x.evaluate_hsv(pixel).x,
+            (x, Saturation) => x.evaluate_hsv(pixel).y,
+            (x, Value) => x.evaluate_hsv(pixel).z,
+            (x, Red) => x.evaluate_rgb(pixel).x,
+            (x, Green) => x.evaluate_rgb(pixel).y,
+            (x, Blue) => x.evaluate_rgb(pixel).z,
+        }
+    }
+
+    pub fn evaluate_hsv_spherical(&self, pixel: &Pixel) -> Vec4 {
+        use self::ColorData::*;
+        match self {
+            ColorRef { color_source } => pixel.color(color_source).point,
+            ImageRef { image_source } => pixel.image(image_source).point,
+            x => hsv2half_spherical(x.evaluate_hsv(pixel)),
         }
     }
 
@@ -188,6 +306,7 @@ impl ColorData {
                 Hue => hue.get_required_sources(graph),
                 Saturation => saturation.get_required_sources(graph),
                 Value => value.get_required_sources(graph),
+                Alpha => {}
                 _ => {
                     hue.get_required_sources(graph);
                     saturation.get_required_sources(graph);
@@ -202,6 +321,7 @@ impl ColorData {
                 Red => red.get_required_sources(graph),
                 Green => green.get_required_sources(graph),
                 Blue => blue.get_required_sources(graph),
+                Alpha => {}
                 _ => {
                     red.get_required_sources(graph);
                     green.get_required_sources(graph);
@@ -242,4 +362,212 @@ impl ColorData {
             &ImageRef { ref image_source } => graph.require_image(image_source.clone()),
         }
     }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        use self::ColorData::*;
+        match self {
+            HSVColor {
+                hue,
+                saturation,
+                value,
+            } => {
+                hue.validate(&format!("{}.hue", path), validation);
+                saturation.validate(&format!("{}.saturation", path), validation);
+                value.validate(&format!("{}.value", path), validation);
+            }
+            RGBColor { red, green, blue } => {
+                red.validate(&format!("{}.red", path), validation);
+                green.validate(&format!("{}.green", path), validation);
+                blue.validate(&format!("{}.blue", path), validation);
+            }
+            ColorRef { .. } | ImageRef { .. } => {}
+        }
+    }
+}
+
+/// Metadata for an input of the generated ISF shader, matched to the data source of the same name
+/// and kind. Anything left out falls back to what ISF does without it.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Input {
+    Float {
+        name: String,
+        #[serde(default)]
+        label: Option<String>,
+        #[serde(default)]
+        default: Option<f32>,
+        #[serde(default)]
+        min: Option<f32>,
+        #[serde(default)]
+        max: Option<f32>,
+    },
+    Color {
+        name: String,
+        #[serde(default)]
+        label: Option<String>,
+        #[serde(default)]
+        default: Option<Color>,
+    },
+    Image {
+        name: String,
+        #[serde(default)]
+        label: Option<String>,
+    },
+}
+
+impl Input {
+    pub fn source(&self) -> DataSource {
+        let (name, kind) = match self {
+            Input::Float { name, .. } => (name, DataSourceKind::Float),
+            Input::Color { name, .. } => (name, DataSourceKind::Color),
+            Input::Image { name, .. } => (name, DataSourceKind::Image),
+        };
+        DataSource {
+            name: name.clone(),
+            kind,
+        }
+    }
+
+    /// The label shown for the input in VDMX, which is the source name unless one is given.
+    pub fn label(&self) -> &str {
+        match self {
+            Input::Float { name, label, .. }
+            | Input::Color { name, label, .. }
+            | Input::Image { name, label } => label.as_ref().unwrap_or(name),
+        }
+    }
+
+    /// Extra fields of the ISF INPUTS entry, each written as a json key and value.
+    pub fn isf_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        match self {
+            Input::Float {
+                default, min, max, ..
+            } => {
+                for &(key, value) in &[("DEFAULT", default), ("MIN", min), ("MAX", max)] {
+                    if let Some(x) = value {
+                        fields.push((key, format!("{:?}", x)));
+                    }
+                }
+            }
+            Input::Color {
+                default: Some(color),
+                ..
+            } => {
+                let channel = |c: u8| format!("{:?}", c as f32 / 255.0);
+                let rgba = [
+                    channel(color.0),
+                    channel(color.1),
+                    channel(color.2),
+                    channel(color.3),
+                ];
+                fields.push(("DEFAULT", format!("[{}]", rgba.join(", "))));
+            }
+            Input::Color { .. } | Input::Image { .. } => {}
+        }
+        fields
+    }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        if let Input::Float {
+            default, min, max, ..
+        } = *self
+        {
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    validation.error(path, format!("MIN {:?} is greater than MAX {:?}", min, max));
+                }
+            }
+            if let Some(default) = default {
+                if min.is_some_and(|min| default < min) || max.is_some_and(|max| default > max) {
+                    validation.error(path, format!("DEFAULT {:?} is outside of MIN and MAX", default));
+                }
+            }
+        }
+    }
+}
+
+/// Where the alpha of every pass of the generated shader comes from.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum AlphaSource {
+    /// Every pixel is fully opaque.
+    Opaque,
+    /// The alpha of an image source at the same pixel.
+    Image(String),
+    Float(FloatData),
+}
+
+/// How the folding machine handles alpha. Images are read with straight alpha unless
+/// premultiplied is set, in which case colors are divided by their alpha before folding and
+/// multiplied by the output alpha afterwards.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Alpha {
+    pub source: AlphaSource,
+    pub premultiplied: bool,
+}
+
+impl Default for Alpha {
+    fn default() -> Self {
+        Alpha {
+            source: AlphaSource::Opaque,
+            premultiplied: false,
+        }
+    }
+}
+
+impl Alpha {
+    pub fn is_default(&self) -> bool {
+        matches!(self.source, AlphaSource::Opaque) && !self.premultiplied
+    }
+
+    pub fn reference_string(&self) -> String {
+        match self.source {
+            AlphaSource::Opaque => String::from("1.0"),
+            AlphaSource::Image(ref name) => format!("img_{}__a", mangle(name)),
+            AlphaSource::Float(ref x) => x.reference_string(),
+        }
+    }
+
+    pub fn evaluate(&self, pixel: &Pixel) -> f32 {
+        match self.source {
+            AlphaSource::Opaque => 1.0,
+            AlphaSource::Image(ref name) => pixel.image(name).alpha,
+            AlphaSource::Float(ref x) => x.evaluate(pixel),
+        }
+    }
+
+    pub fn get_required_sources(&self, graph: &mut DataDependencyGraph) {
+        match self.source {
+            AlphaSource::Opaque => {}
+            AlphaSource::Image(ref name) => {
+                graph.require_image_channel(name.clone(), ColorProperties::Alpha)
+            }
+            AlphaSource::Float(ref x) => x.get_required_sources(graph),
+        }
+    }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        if let AlphaSource::Float(ref x) = self.source {
+            x.validate(&format!("{}.source", path), validation)
+        }
+    }
+
+    /// A color read from an image, as the compute functions see it.
+    pub fn read(&self, rgb: Vec3, alpha: f32) -> Sample {
+        let rgb = if self.premultiplied {
+            rgb / alpha.max(EPSILON)
+        } else {
+            rgb
+        };
+        Sample::from_rgba(rgb, alpha)
+    }
+
+    /// The color written out for a folded color and its alpha.
+    pub fn write(&self, rgb: Vec3, alpha: f32) -> Vec3 {
+        if self.premultiplied {
+            rgb * alpha
+        } else {
+            rgb
+        }
+    }
 }
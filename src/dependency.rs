@@ -1,12 +1,12 @@
 use crate::color::ColorProperties;
 use crate::data::{DataSource, DataSourceKind};
-use std::collections::HashMap;
 
 #[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub struct DataDependency {
     pub require_rgb: bool,
     pub require_hsv: bool,
     pub require_point: bool,
+    pub require_alpha: bool,
 }
 
 impl DataDependency {
@@ -15,6 +15,7 @@ impl DataDependency {
             require_hsv: true,
             require_rgb: false,
             require_point: false,
+            require_alpha: false,
         }
     }
 
@@ -23,6 +24,7 @@ impl DataDependency {
             require_hsv: false,
             require_rgb: true,
             require_point: false,
+            require_alpha: false,
         }
     }
 
@@ -31,41 +33,81 @@ impl DataDependency {
             require_hsv: false,
             require_rgb: false,
             require_point: true,
+            require_alpha: false,
+        }
+    }
+
+    pub fn alpha() -> Self {
+        DataDependency {
+            require_hsv: false,
+            require_rgb: false,
+            require_point: false,
+            require_alpha: true,
         }
     }
 }
 
-pub struct DataDependencyGraph(HashMap<DataSource, DataDependency>);
+/// DataDependencyGraph records every data source required by part of a folding machine and how
+/// it is used. Sources are kept in the order they were first required, so shader inputs and
+/// function parameters come out in the same order as in the descriptor on every run.
+pub struct DataDependencyGraph(Vec<(DataSource, DataDependency)>);
+
+impl Default for DataDependencyGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl DataDependencyGraph {
     pub fn new() -> Self {
-        DataDependencyGraph(HashMap::new())
+        DataDependencyGraph(Vec::new())
     }
 
-    fn insert_point(&mut self, source: DataSource) {
+    pub fn get(&self, source: &DataSource) -> Option<&DataDependency> {
         self.0
-            .entry(source)
-            .and_modify(|x| x.require_point = true)
-            .or_insert(DataDependency::point());
+            .iter()
+            .find(|(x, _)| x == source)
+            .map(|(_, dependency)| dependency)
     }
 
-    fn insert_hsv(&mut self, source: DataSource) {
+    fn get_mut(&mut self, source: &DataSource) -> Option<&mut DataDependency> {
         self.0
-            .entry(source)
-            .and_modify(|x| x.require_point = true)
-            .or_insert(DataDependency::hsv());
+            .iter_mut()
+            .find(|(x, _)| x == source)
+            .map(|(_, dependency)| dependency)
+    }
+
+    /// Mark source as required, creating it with the given dependency or updating the existing
+    /// dependency with modify.
+    fn insert_with<F>(&mut self, source: DataSource, dependency: DataDependency, modify: F)
+    where
+        F: FnOnce(&mut DataDependency),
+    {
+        match self.get_mut(&source) {
+            Some(existing) => modify(existing),
+            None => self.0.push((source, dependency)),
+        }
+    }
+
+    fn insert_point(&mut self, source: DataSource) {
+        self.insert_with(source, DataDependency::point(), |x| x.require_point = true)
+    }
+
+    fn insert_hsv(&mut self, source: DataSource) {
+        self.insert_with(source, DataDependency::hsv(), |x| x.require_point = true)
     }
 
     fn insert_rgb(&mut self, source: DataSource) {
-        self.0
-            .entry(source)
-            .and_modify(|x| x.require_point = true)
-            .or_insert(DataDependency::rgb());
+        self.insert_with(source, DataDependency::rgb(), |x| x.require_point = true)
+    }
+
+    fn insert_alpha(&mut self, source: DataSource) {
+        self.insert_with(source, DataDependency::alpha(), |x| x.require_alpha = true)
     }
 
     pub fn require_color(&mut self, name: String) {
         self.insert_point(DataSource {
-            name: name,
+            name,
             kind: DataSourceKind::Color,
         })
     }
@@ -74,11 +116,15 @@ impl DataDependencyGraph {
         use crate::color::ColorProperties::*;
         match property {
             Red | Blue | Green => self.insert_rgb(DataSource {
-                name: name,
+                name,
                 kind: DataSourceKind::Color,
             }),
             Hue | Saturation | Value => self.insert_hsv(DataSource {
-                name: name,
+                name,
+                kind: DataSourceKind::Color,
+            }),
+            Alpha => self.insert_alpha(DataSource {
+                name,
                 kind: DataSourceKind::Color,
             }),
         }
@@ -86,7 +132,7 @@ impl DataDependencyGraph {
 
     pub fn require_image(&mut self, name: String) {
         self.insert_point(DataSource {
-            name: name,
+            name,
             kind: DataSourceKind::Image,
         })
     }
@@ -95,45 +141,60 @@ impl DataDependencyGraph {
         use crate::color::ColorProperties::*;
         match property {
             Red | Blue | Green => self.insert_rgb(DataSource {
-                name: name,
+                name,
                 kind: DataSourceKind::Image,
             }),
             Hue | Saturation | Value => self.insert_hsv(DataSource {
-                name: name,
+                name,
+                kind: DataSourceKind::Image,
+            }),
+            Alpha => self.insert_alpha(DataSource {
+                name,
                 kind: DataSourceKind::Image,
             }),
         }
     }
 
     pub fn require_float(&mut self, name: String) {
-        self.0.insert(
+        self.insert_with(
             DataSource {
-                name: name,
+                name,
                 kind: DataSourceKind::Float,
             },
             DataDependency::default(),
+            |_| {},
         );
     }
 
-    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, DataSource, DataDependency> {
-        self.0.iter()
+    /// Mark every image source named after one of the given stages as a reference to the output
+    /// of that stage.
+    pub fn resolve_stages(&mut self, stages: &[String]) {
+        for (source, _) in self.0.iter_mut() {
+            if source.kind == DataSourceKind::Image && stages.contains(&source.name) {
+                source.kind = DataSourceKind::Stage;
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&DataSource, &DataDependency)> {
+        self.0.iter().map(|(source, dependency)| (source, dependency))
     }
 
-    pub fn keys(&self) -> std::collections::hash_map::Keys<'_, DataSource, DataDependency> {
-        self.0.keys()
+    pub fn keys(&self) -> impl Iterator<Item = &DataSource> {
+        self.0.iter().map(|(source, _)| source)
     }
 
-    pub fn values(&self) -> std::collections::hash_map::Values<'_, DataSource, DataDependency> {
-        self.0.values()
+    pub fn values(&self) -> impl Iterator<Item = &DataDependency> {
+        self.0.iter().map(|(_, dependency)| dependency)
     }
 }
 
 impl IntoIterator for DataDependencyGraph {
-    type Item = <HashMap<DataSource, DataDependency> as IntoIterator>::Item;
-    type IntoIter = std::collections::hash_map::IntoIter<DataSource, DataDependency>;
+    type Item = (DataSource, DataDependency);
+    type IntoIter = std::vec::IntoIter<(DataSource, DataDependency)>;
 
     #[inline]
-    fn into_iter(self) -> std::collections::hash_map::IntoIter<DataSource, DataDependency> {
+    fn into_iter(self) -> std::vec::IntoIter<(DataSource, DataDependency)> {
         self.0.into_iter()
     }
 }
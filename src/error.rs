@@ -0,0 +1,146 @@
+//! # Errors
+//! Every failure colorfold can report, from reading descriptors through to generating shaders.
+
+use glsl::ShaderError;
+use validation::ValidationError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub type Result<T> = std::result::Result<T, ColorfoldError>;
+
+pub enum ColorfoldError {
+    /// Reading or writing a file failed.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// A descriptor is not valid ron.
+    Ron {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A descriptor is not valid json.
+    Json {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A descriptor parsed but does not describe something colorfold can do.
+    Semantic(String),
+    /// Problems found by validating a descriptor, each naming the node it was found at.
+    Invalid(Vec<ValidationError>),
+    /// The shader generated for a descriptor is not valid GLSL.
+    Codegen(Vec<ShaderError>),
+    /// Some of several files failed, each of which has already been reported.
+    Failed(usize),
+}
+
+impl ColorfoldError {
+    pub fn io(path: &Path, error: std::io::Error) -> Self {
+        ColorfoldError::Io {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+
+    pub fn semantic<S: Into<String>>(message: S) -> Self {
+        ColorfoldError::Semantic(message.into())
+    }
+
+    pub fn json(path: &Path, error: &serde_json::Error) -> Self {
+        ColorfoldError::Json {
+            path: path.to_path_buf(),
+            line: error.line(),
+            column: error.column(),
+            message: error
+                .to_string()
+                .trim_end_matches(&format!(" at line {} column {}", error.line(), error.column()))
+                .to_owned(),
+        }
+    }
+
+    /// A ron error, positioned at offset into text when the error does not carry a position of
+    /// its own.
+    pub fn ron(path: &Path, error: ron::de::Error, text: &str, offset: usize) -> Self {
+        let (line, column, message) = match error {
+            ron::de::Error::Parser(_, position) => {
+                let message = error.to_string();
+                let message = message.trim_start_matches(&format!("{}: ", position));
+                (position.line, position.col, message.to_owned())
+            }
+            ron::de::Error::Message(message) | ron::de::Error::IoError(message) => {
+                let before = &text[..offset.min(text.len())];
+                let line = before.matches('\n').count() + 1;
+                let column = before.len() - before.rfind('\n').map_or(0, |x| x + 1) + 1;
+                (line, column, message)
+            }
+        };
+        ColorfoldError::Ron {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ColorfoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorfoldError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ColorfoldError::Ron {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Malformed ron in {}:{}:{}: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            ColorfoldError::Json {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Malformed json in {}:{}:{}: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            ColorfoldError::Semantic(message) => write!(f, "{}", message),
+            ColorfoldError::Invalid(errors) => {
+                write!(f, "Invalid folding machine")?;
+                for error in errors {
+                    write!(f, "\n    {}", error)?;
+                }
+                Ok(())
+            }
+            ColorfoldError::Codegen(errors) => {
+                write!(f, "Generated invalid GLSL")?;
+                for error in errors {
+                    write!(f, "\n    {}", error)?;
+                }
+                Ok(())
+            }
+            ColorfoldError::Failed(count) => write!(f, "{} of the given files failed", count),
+        }
+    }
+}
+
+impl fmt::Debug for ColorfoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ColorfoldError {}
@@ -0,0 +1,825 @@
+//! # Evaluation
+//! A cpu reference implementation of the shader library in `shaders/lib.fs`. Every function here
+//! mirrors the glsl function of the same name so that a FoldingMachine evaluated on the cpu
+//! produces the same colors as the generated shader does in VDMX.
+
+use std::collections::HashMap;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+use color::Color;
+
+pub const EPSILON: f32 = 0.000_000_1;
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// A point in the half spherical color space along with the weight (or null marker) used by the
+/// palette transformation routines. A `w` below -0.5 marks the value as null.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn mix(self, other: Vec3, percent: f32) -> Vec3 {
+        self + (other - self) * percent
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+}
+
+impl Vec4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Vec4 { x, y, z, w }
+    }
+
+    /// The null value, equivalent to `vec4(0, 0, 0, -1)` in the shader library.
+    pub fn null() -> Self {
+        Vec4::new(0.0, 0.0, 0.0, -1.0)
+    }
+
+    pub fn is_null(self) -> bool {
+        self.w < -0.5
+    }
+
+    pub fn xyz(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn mix(self, other: Vec4, percent: f32) -> Vec4 {
+        self.xyz()
+            .mix(other.xyz(), percent)
+            .extend(self.w + (other.w - self.w) * percent)
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Vec3;
+    fn div(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x / scale, self.y / scale, self.z / scale)
+    }
+}
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// ================================================================================================
+// == Color conversions                                                                          ==
+// ================================================================================================
+
+pub fn rgb2hsv(c: Vec3) -> Vec3 {
+    let k = (0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+    let p = if c.y < c.z {
+        (c.z, c.y, k.3, k.2)
+    } else {
+        (c.y, c.z, k.0, k.1)
+    };
+    let q = if c.x < p.0 {
+        (p.0, p.1, p.3, c.x)
+    } else {
+        (c.x, p.1, p.2, p.0)
+    };
+
+    let d = q.0 - q.3.min(q.1);
+    let e = 1.0e-10;
+    Vec3::new(
+        (q.2 + (q.3 - q.1) / (6.0 * d + e)).abs(),
+        d / (q.0 + e),
+        q.0,
+    )
+}
+
+pub fn hsv2rgb(c: Vec3) -> Vec3 {
+    let channel = |k: f32| {
+        let p = (fract(c.x + k) * 6.0 - 3.0).abs();
+        let clamped = (p - 1.0).clamp(0.0, 1.0);
+        c.z * (1.0 + (clamped - 1.0) * c.y)
+    };
+    Vec3::new(channel(1.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+pub fn hsv2half_spherical(color: Vec3) -> Vec4 {
+    let sat_angle = color.y * std::f32::consts::FRAC_PI_2;
+    let hue_angle = color.x * 2.0 * std::f32::consts::PI;
+    let hue_sat = Vec3::new(
+        sat_angle.sin() * hue_angle.cos(),
+        sat_angle.sin() * hue_angle.sin(),
+        sat_angle.cos(),
+    );
+    (hue_sat * color.z).extend(0.0)
+}
+
+pub fn half_spherical2hsv(color: Vec3) -> Vec3 {
+    let mut hue_angle = color.y.atan2(color.x);
+    let sat_angle = Vec3::new(color.x, color.y, 0.0).length().atan2(color.z.abs());
+
+    if hue_angle < 0.0 {
+        hue_angle += 2.0 * std::f32::consts::PI;
+    }
+
+    Vec3::new(
+        hue_angle / (2.0 * std::f32::consts::PI),
+        sat_angle * 2.0 / std::f32::consts::PI,
+        color.length(),
+    )
+}
+
+// ================================================================================================
+// == Rotation calculation routines                                                              ==
+// ================================================================================================
+
+fn rotation_axis(start: Vec3, end: Vec3) -> Vec3 {
+    let mut axis = start.cross(end);
+    if axis.dot(axis) < EPSILON {
+        axis = start.cross(Vec3::new(0.0, 1.0, 0.0));
+        if axis.dot(axis) < EPSILON {
+            axis = start.cross(Vec3::new(1.0, 0.0, 0.0));
+        }
+    }
+    axis / axis.length()
+}
+
+fn angle_between(start: Vec3, end: Vec3) -> f32 {
+    (start.dot(end) / (start.length() * end.length()))
+        .clamp(-1.0, 1.0)
+        .acos()
+}
+
+pub fn get_axis_angle_quat(axis: Vec3, angle: f32) -> Vec4 {
+    (axis * (angle / 2.0).sin()).extend((angle / 2.0).cos())
+}
+
+pub fn get_rotation_quat(start: Vec3, end: Vec3) -> Vec4 {
+    if start.length() <= EPSILON || end.length() <= EPSILON {
+        return Vec4::new(0.0, 0.0, 0.0, 1.0);
+    }
+    get_axis_angle_quat(rotation_axis(start, end), angle_between(start, end))
+}
+
+pub fn rotate_by_quat(position: Vec3, quaternion: Vec4) -> Vec3 {
+    let q = quaternion.xyz();
+    position + q.cross(q.cross(position) + position * quaternion.w) * 2.0
+}
+
+pub fn lin_interp(start: Vec4, end: Vec4, percent: f32) -> Vec4 {
+    if start.is_null() || end.is_null() {
+        return Vec4::null();
+    }
+    start.mix(end, percent)
+}
+
+pub fn lin_interp_bounded(start: Vec4, end: Vec4, percent: f32) -> Vec4 {
+    if !(0.0..=1.0).contains(&percent) {
+        return Vec4::null();
+    }
+    start.mix(end, percent)
+}
+
+pub fn rot_interp(start_4: Vec4, end_4: Vec4, percent: f32) -> Vec4 {
+    if start_4.is_null() || end_4.is_null() {
+        return Vec4::null();
+    }
+    let start = start_4.xyz();
+    let end = end_4.xyz();
+
+    if start.length() <= EPSILON {
+        return (end * percent).extend(0.0);
+    }
+    if end.length() <= EPSILON {
+        return (start * (1.0 - percent)).extend(0.0);
+    }
+
+    let angle = angle_between(start, end);
+    let rotation_quat = get_axis_angle_quat(rotation_axis(start, end), angle * percent);
+    let start_length = start.length();
+    let new_length = start_length + (end.length() - start_length) * percent;
+
+    rotate_by_quat(start * new_length / start_length, rotation_quat).extend(0.0)
+}
+
+pub fn rot_interp_bounded(start: Vec4, end: Vec4, percent: f32) -> Vec4 {
+    if !(0.0..=1.0).contains(&percent) {
+        return Vec4::null();
+    }
+    rot_interp(start, end, percent)
+}
+
+// ================================================================================================
+// == Palette transformation routines                                                            ==
+// ================================================================================================
+
+pub fn point_point(in_vec: Vec4, start: Vec4, end: Vec4) -> Vec4 {
+    if in_vec.is_null() || start.is_null() || end.is_null() {
+        return Vec4::null();
+    }
+
+    let rotation = get_rotation_quat(start.xyz(), end.xyz());
+    let dist_vec = start.xyz() - in_vec.xyz();
+    rotate_by_quat(in_vec.xyz(), rotation).extend(dist_vec.dot(dist_vec))
+}
+
+/// point_point pivoting about center instead of black.
+pub fn point_point_centered(in_vec: Vec4, start: Vec4, end: Vec4, center: Vec4) -> Vec4 {
+    if in_vec.is_null() || start.is_null() || end.is_null() || center.is_null() {
+        return Vec4::null();
+    }
+
+    let rotation = get_rotation_quat(start.xyz() - center.xyz(), end.xyz() - center.xyz());
+    let dist_vec = start.xyz() - in_vec.xyz();
+    (center.xyz() + rotate_by_quat(in_vec.xyz() - center.xyz(), rotation))
+        .extend(dist_vec.dot(dist_vec))
+}
+
+pub fn point_translate(in_vec: Vec4, start: Vec4, end: Vec4) -> Vec4 {
+    if in_vec.is_null() || start.is_null() || end.is_null() {
+        return Vec4::null();
+    }
+
+    let dist_vec = start.xyz() - in_vec.xyz();
+    (in_vec.xyz() + end.xyz() - start.xyz()).extend(dist_vec.dot(dist_vec))
+}
+
+/// Pull in_vec towards center, or push it away for a negative strength, by strength times a
+/// falloff which is 1 at center and 0 beyond radius. The falloff is linear (0), smooth (1) or
+/// constant (2).
+pub fn density_shift(in_vec: Vec4, center: Vec4, strength: f32, radius: f32, falloff: i32) -> Vec4 {
+    if in_vec.is_null() || center.is_null() {
+        return Vec4::null();
+    }
+
+    let dist_vec = center.xyz() - in_vec.xyz();
+    let dist = dist_vec.length();
+    let amount = if radius <= EPSILON || dist >= radius {
+        0.0
+    } else {
+        match falloff {
+            0 => 1.0 - dist / radius,
+            1 => 1.0 - smoothstep(0.0, radius, dist),
+            _ => 1.0,
+        }
+    };
+    clamp_length(in_vec.xyz() + dist_vec * (strength * amount)).extend(dist_vec.dot(dist_vec))
+}
+
+/// Reflect in_vec across the plane through point with the given normal. Folding (0) only reflects
+/// vectors on the side the normal points to, while mirroring (1) reflects every vector.
+pub fn point_reflect(in_vec: Vec4, point: Vec4, normal: Vec4, mode: i32) -> Vec4 {
+    if in_vec.is_null() || point.is_null() || normal.is_null() || normal.xyz().length() < EPSILON
+    {
+        return Vec4::null();
+    }
+
+    let normal = normal.xyz() / normal.xyz().length();
+    let dist_vec = point.xyz() - in_vec.xyz();
+    let side = (in_vec.xyz() - point.xyz()).dot(normal);
+    let reflected = if mode == 0 && side <= 0.0 {
+        in_vec.xyz()
+    } else {
+        in_vec.xyz() - normal * (2.0 * side)
+    };
+    clamp_length(reflected).extend(dist_vec.dot(dist_vec))
+}
+
+/// Rotate in_vec about the grey axis by angle turns, shifting its hue by angle.
+pub fn point_twist(in_vec: Vec4, angle: f32) -> Vec4 {
+    if in_vec.is_null() {
+        return Vec4::null();
+    }
+
+    let rotation = get_axis_angle_quat(Vec3::new(0.0, 0.0, 1.0), angle * 2.0 * std::f32::consts::PI);
+    rotate_by_quat(in_vec.xyz(), rotation).extend(0.0)
+}
+
+fn clamp_length(position: Vec3) -> Vec3 {
+    if position.dot(position) > 1.0 {
+        position / position.length()
+    } else {
+        position
+    }
+}
+
+pub fn clamp(in_vec: Vec4, _start: f32, _end: f32) -> Vec4 {
+    clamp_length(in_vec.xyz()).extend(in_vec.w)
+}
+
+pub fn ratio_clamp(in_vec: Vec4, start: f32, end: f32) -> Vec4 {
+    if start < EPSILON {
+        Vec4::new(0.0, 0.0, 0.0, in_vec.w)
+    } else {
+        clamp_length(in_vec.xyz() * (end / start)).extend(in_vec.w)
+    }
+}
+
+pub fn ratio_blend(in_vec: Vec4, start: f32, end: f32, knee: f32, dark: i32, floor: f32) -> Vec4 {
+    let ratio = ratio_clamp(in_vec, start.max(EPSILON), end);
+    let in_len = in_vec.xyz().length();
+    let dark_len = match dark {
+        0 => in_len,
+        1 => end + (1.0 - end) * ((in_len - start) / (1.0 - start).max(EPSILON)),
+        _ => in_len.max(floor),
+    }
+    .clamp(0.0, 1.0);
+
+    let direction = if in_len < EPSILON {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        in_vec.xyz() / in_len
+    };
+    let weight = if knee < EPSILON {
+        1.0
+    } else {
+        smoothstep(0.0, knee, start)
+    };
+    (direction * dark_len)
+        .mix(ratio.xyz(), weight)
+        .extend(in_vec.w)
+}
+
+fn soft_knee(len: f32, knee: f32) -> f32 {
+    let headroom = 1.0 - knee;
+    if len <= knee {
+        return len;
+    }
+    if headroom < EPSILON {
+        return 1.0;
+    }
+    let over = len - knee;
+    knee + over / (1.0 + over / headroom)
+}
+
+fn ratio_scaled(in_vec: Vec4, start: f32, end: f32, ratio: bool) -> Vec3 {
+    if !ratio {
+        in_vec.xyz()
+    } else if start < EPSILON {
+        Vec3::new(0.0, 0.0, 0.0)
+    } else {
+        in_vec.xyz() * (end / start)
+    }
+}
+
+pub fn soft_value(in_vec: Vec4, start: f32, end: f32, knee: f32, ratio: bool) -> Vec4 {
+    let position = ratio_scaled(in_vec, start, end, ratio);
+    let len = position.length();
+    if len < EPSILON {
+        return Vec4::new(0.0, 0.0, 0.0, in_vec.w);
+    }
+    (position * (soft_knee(len, knee) / len)).extend(in_vec.w)
+}
+
+pub fn soft_saturation(in_vec: Vec4, start: f32, end: f32, knee: f32, ratio: bool) -> Vec4 {
+    let position = ratio_scaled(in_vec, start, end, ratio);
+    let len = position.length();
+    if len < EPSILON {
+        return Vec4::new(0.0, 0.0, 0.0, in_vec.w);
+    }
+    let new_len = soft_knee(len, knee);
+    let planar = (position.x * position.x + position.y * position.y).sqrt();
+    if planar < EPSILON {
+        return (position * (new_len / len)).extend(in_vec.w);
+    }
+
+    let hue_angle = position.y.atan2(position.x);
+    let sat_angle = planar.atan2(position.z.abs()) * new_len / len;
+    let z = if position.z < 0.0 {
+        -sat_angle.cos()
+    } else {
+        sat_angle.cos()
+    };
+    let direction = Vec3::new(
+        sat_angle.sin() * hue_angle.cos(),
+        sat_angle.sin() * hue_angle.sin(),
+        z,
+    );
+    (direction * new_len).extend(in_vec.w)
+}
+
+pub fn bezier_loose(in_vec: Vec4, start_mid: f32, end_mid: f32) -> Vec4 {
+    let in_len = in_vec.xyz().length();
+    if in_len < EPSILON {
+        return Vec4::new(0.0, 0.0, 0.0, in_vec.w);
+    }
+
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let percent = mix(mix(0.0, start_mid, in_len), mix(start_mid, 1.0, in_len), in_len);
+    let new_length = mix(mix(0.0, end_mid, percent), mix(end_mid, 1.0, percent), percent);
+    (in_vec.xyz() * (new_length / in_len)).extend(in_vec.w)
+}
+
+pub fn bezier_strict(in_vec: Vec4, start_mid: f32, end_mid: f32) -> Vec4 {
+    let in_len = in_vec.xyz().length();
+    if in_len < EPSILON {
+        return Vec4::new(0.0, 0.0, 0.0, in_vec.w);
+    }
+
+    let s = start_mid.clamp(0.0, 1.0);
+    let e = end_mid.clamp(0.0, 1.0);
+    let x = in_len.min(1.0);
+
+    let denominator = e * (1.0 - s) + s * (1.0 - e);
+    let slope = if denominator < EPSILON {
+        1.0
+    } else {
+        2.0 * e * (1.0 - e) / denominator
+    };
+
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let new_length = if x <= s {
+        let t = x / s;
+        let control = e - slope * s / 2.0;
+        mix(mix(0.0, control, t), mix(control, e, t), t)
+    } else if s > 1.0 - EPSILON {
+        1.0
+    } else {
+        let t = (x - s) / (1.0 - s);
+        let control = e + slope * (1.0 - s) / 2.0;
+        mix(mix(e, control, t), mix(control, 1.0, t), t)
+    };
+    (in_vec.xyz() * (new_length / in_len)).extend(in_vec.w)
+}
+
+/// InvWeightedSum accumulates transformed vectors the same way the generated compute functions
+/// do. Vectors with zero weight take priority over all others, otherwise each vector is weighted
+/// by the inverse of its weight.
+#[derive(Default)]
+pub struct InvWeightedSum {
+    total_inv_weight: f32,
+    total_inv_weight_vecs: Vec3,
+    num_zeros: u32,
+    total_zeros: Vec3,
+}
+
+impl InvWeightedSum {
+    pub fn add(&mut self, value: Vec4) {
+        if value.is_null() {
+            return;
+        }
+        if value.w < EPSILON {
+            self.num_zeros += 1;
+            self.total_zeros += value.xyz();
+        } else {
+            self.total_inv_weight += 1.0 / value.w;
+            self.total_inv_weight_vecs += value.xyz() * (1.0 / value.w);
+        }
+    }
+
+    pub fn total(&self) -> Vec4 {
+        if self.num_zeros > 0 {
+            (self.total_zeros / self.num_zeros as f32).extend(0.0)
+        } else if self.total_inv_weight > EPSILON {
+            (self.total_inv_weight_vecs * (1.0 / self.total_inv_weight)).extend(0.0)
+        } else {
+            Vec4::null()
+        }
+    }
+}
+
+/// Convert the output of a compute stage back into rgb, as the generated main function does.
+pub fn folded_to_rgb(folded: Vec4) -> Vec3 {
+    hsv2rgb(half_spherical2hsv(folded.xyz()))
+}
+
+// ================================================================================================
+// == Data sources                                                                               ==
+// ================================================================================================
+
+/// Sample holds a color in every form a compute function may ask for it.
+#[derive(Clone, Copy, Default)]
+pub struct Sample {
+    pub rgb: Vec3,
+    pub hsv: Vec3,
+    pub point: Vec4,
+    pub alpha: f32,
+}
+
+impl Sample {
+    /// A fully opaque sample.
+    pub fn from_rgb(rgb: Vec3) -> Self {
+        Sample::from_rgba(rgb, 1.0)
+    }
+
+    pub fn from_rgba(rgb: Vec3, alpha: f32) -> Self {
+        let hsv = rgb2hsv(rgb);
+        Sample {
+            rgb,
+            hsv,
+            point: hsv2half_spherical(hsv),
+            alpha,
+        }
+    }
+}
+
+impl<'a> From<&'a Color> for Sample {
+    fn from(color: &'a Color) -> Self {
+        let &Color(r, g, b, a) = color;
+        Sample::from_rgba(
+            Vec3::new(
+                f32::from(r) / 255.0,
+                f32::from(g) / 255.0,
+                f32::from(b) / 255.0,
+            ),
+            f32::from(a) / 255.0,
+        )
+    }
+}
+
+/// Pixel holds the value of every data source at a single pixel. Sources which were never bound
+/// evaluate to zero, just like an unset ISF input.
+#[derive(Default)]
+pub struct Pixel {
+    pub images: HashMap<String, Sample>,
+    pub colors: HashMap<String, Sample>,
+    pub floats: HashMap<String, f32>,
+}
+
+impl Pixel {
+    /// A pixel with the given colors and floats bound and no images.
+    pub fn new(colors: &HashMap<String, Color>, floats: &HashMap<String, f32>) -> Self {
+        Pixel {
+            images: HashMap::new(),
+            colors: colors
+                .iter()
+                .map(|(name, color)| (name.clone(), Sample::from(color)))
+                .collect(),
+            floats: floats.clone(),
+        }
+    }
+
+    pub fn image(&self, name: &str) -> Sample {
+        self.images.get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn color(&self, name: &str) -> Sample {
+        self.colors.get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        self.floats.get(name).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAIRS: [(f32, f32); 6] = [
+        (0.3, 0.7),
+        (0.7, 0.3),
+        (0.5, 0.5),
+        (0.1, 0.9),
+        (0.9, 0.1),
+        (0.25, 0.0),
+    ];
+
+    fn assert_close3(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "got {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_close4(actual: Vec4, expected: Vec4) {
+        assert_close3(actual.xyz(), expected.xyz());
+        assert!(
+            (actual.w - expected.w).abs() < 1e-5,
+            "got weight {}, expected {}",
+            actual.w,
+            expected.w
+        );
+    }
+
+    #[test]
+    fn color_conversions_match_lib() {
+        let pairs = [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 1.0)),
+            (Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0 / 3.0, 1.0, 1.0)),
+            (Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 0.0, 0.5)),
+            (
+                Vec3::new(0.2, 0.4, 0.8),
+                Vec3::new(220.0 / 360.0, 0.75, 0.8),
+            ),
+        ];
+        for &(rgb, hsv) in pairs.iter() {
+            assert_close3(rgb2hsv(rgb), hsv);
+            assert_close3(hsv2rgb(hsv), rgb);
+        }
+    }
+
+    #[test]
+    fn half_spherical_matches_lib() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let pairs = [
+            (Vec3::new(0.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.25, 1.0, 0.5), Vec3::new(0.0, 0.5, 0.0)),
+            (Vec3::new(0.0, 0.0, 0.7), Vec3::new(0.0, 0.0, 0.7)),
+            (Vec3::new(0.5, 0.5, 1.0), Vec3::new(-half, 0.0, half)),
+        ];
+        for &(hsv, point) in pairs.iter() {
+            assert_close4(hsv2half_spherical(hsv), point.extend(0.0));
+            assert_close3(half_spherical2hsv(point), hsv);
+        }
+    }
+
+    #[test]
+    fn interpolation_matches_lib() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let x = Vec4::new(1.0, 0.0, 0.0, 0.0);
+        let y = Vec4::new(0.0, 1.0, 0.0, 0.0);
+
+        assert_close4(lin_interp(x, y, 0.5), Vec4::new(0.5, 0.5, 0.0, 0.0));
+        assert_close4(lin_interp(x, Vec4::null(), 0.5), Vec4::null());
+        assert_close4(lin_interp_bounded(x, y, 1.5), Vec4::null());
+        assert_close4(rot_interp(x, y, 0.5), Vec4::new(half, half, 0.0, 0.0));
+        assert_close4(
+            rot_interp(x, Vec4::new(0.0, 0.5, 0.0, 0.0), 0.5),
+            Vec4::new(0.75 * half, 0.75 * half, 0.0, 0.0),
+        );
+        assert_close4(rot_interp_bounded(x, y, -0.5), Vec4::null());
+    }
+
+    #[test]
+    fn transformations_match_lib() {
+        let x = Vec4::new(1.0, 0.0, 0.0, 0.0);
+        let y = Vec4::new(0.0, 1.0, 0.0, 0.0);
+        let z = Vec4::new(0.0, 0.0, 1.0, 0.0);
+
+        assert_close4(point_point(x, x, y), Vec4::new(0.0, 1.0, 0.0, 0.0));
+        assert_close4(point_point(z, x, y), Vec4::new(0.0, 0.0, 1.0, 2.0));
+        assert_close4(point_point(x, Vec4::null(), y), Vec4::null());
+        assert_close4(
+            point_translate(
+                Vec4::new(0.1, 0.2, 0.3, 0.0),
+                Vec4::new(0.0, 0.0, 0.5, 0.0),
+                Vec4::new(0.0, 0.0, 0.7, 0.0),
+            ),
+            Vec4::new(0.1, 0.2, 0.5, 0.09),
+        );
+        assert_close4(
+            density_shift(
+                Vec4::new(0.0, 0.0, 0.2, 0.0),
+                Vec4::new(0.0, 0.0, 0.6, 0.0),
+                0.5,
+                1.0,
+                2,
+            ),
+            Vec4::new(0.0, 0.0, 0.4, 0.16),
+        );
+
+        let plane = Vec4::new(0.0, 0.0, 0.5, 0.0);
+        let above = Vec4::new(0.1, 0.0, 0.8, 0.0);
+        let below = Vec4::new(0.1, 0.0, 0.2, 0.0);
+        assert_close4(
+            point_reflect(above, plane, z, 0),
+            Vec4::new(0.1, 0.0, 0.2, 0.1),
+        );
+        assert_close4(
+            point_reflect(below, plane, z, 0),
+            Vec4::new(0.1, 0.0, 0.2, 0.1),
+        );
+        assert_close4(
+            point_reflect(below, plane, z, 1),
+            Vec4::new(0.1, 0.0, 0.8, 0.1),
+        );
+
+        assert_close4(point_twist(x, 0.25), Vec4::new(0.0, 1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn clamps_match_lib() {
+        let grey = Vec4::new(0.0, 0.0, 0.4, 3.0);
+        assert_close4(
+            clamp(Vec4::new(0.0, 2.0, 0.0, 3.0), 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 3.0),
+        );
+        assert_close4(ratio_clamp(grey, 0.5, 0.75), Vec4::new(0.0, 0.0, 0.6, 3.0));
+        assert_close4(ratio_clamp(grey, 0.2, 1.0), Vec4::new(0.0, 0.0, 1.0, 3.0));
+        assert_close4(ratio_clamp(grey, 0.0, 1.0), Vec4::new(0.0, 0.0, 0.0, 3.0));
+        assert_close4(
+            bezier_loose(Vec4::new(0.0, 0.0, 0.5, 3.0), 0.25, 0.75),
+            Vec4::new(0.0, 0.0, 0.492_187_5, 3.0),
+        );
+    }
+
+    #[test]
+    fn inv_weighted_sum_matches_lib() {
+        let mut sum = InvWeightedSum::default();
+        assert_close4(sum.total(), Vec4::null());
+
+        sum.add(Vec4::new(1.0, 0.0, 0.0, 1.0));
+        sum.add(Vec4::new(0.0, 1.0, 0.0, 3.0));
+        sum.add(Vec4::null());
+        assert_close4(sum.total(), Vec4::new(0.75, 0.25, 0.0, 0.0));
+
+        sum.add(Vec4::new(0.0, 0.0, 1.0, 0.0));
+        assert_close4(sum.total(), Vec4::new(0.0, 0.0, 1.0, 0.0));
+    }
+
+    fn strict_length(len: f32, start: f32, end: f32) -> f32 {
+        let direction = Vec3::new(0.6, 0.0, 0.8);
+        bezier_strict((direction * len).extend(0.0), start, end)
+            .xyz()
+            .length()
+    }
+
+    #[test]
+    fn bezier_strict_maps_start_to_end() {
+        for &(start, end) in PAIRS.iter() {
+            let mapped = strict_length(start, start, end);
+            assert!(
+                (mapped - end).abs() < 1e-5,
+                "{} mapped to {} instead of {}",
+                start,
+                mapped,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn bezier_strict_is_monotone() {
+        for &(start, end) in PAIRS.iter() {
+            let mut previous = 0.0;
+            for i in 1..=200 {
+                let length = strict_length(i as f32 / 200.0, start, end);
+                assert!(
+                    length >= previous - 1e-6,
+                    "curve for ({}, {}) falls at {}",
+                    start,
+                    end,
+                    i as f32 / 200.0
+                );
+                previous = length;
+            }
+        }
+    }
+}
@@ -1,17 +1,222 @@
+use color::ColorProperties;
+use curve::{self, Interpolation};
+use data::{ColorData, FloatData};
 use dependency::DataDependencyGraph;
+use evaluation::{
+    bezier_loose, bezier_strict, clamp, density_shift, point_point, point_point_centered,
+    point_reflect, point_translate, point_twist, ratio_blend, ratio_clamp, soft_saturation,
+    soft_value, InvWeightedSum, Pixel, Vec4,
+};
 use geometry::Geom0D;
+use glsl::{mangle, FunctionNames};
+use validation::Validation;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Summation {
     InvWeighted,
 }
 
-#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub enum Scale {
     Clamp,
     RatioClamp,
     BezierLoose,
-    // BezierStrict, TODO: Implement a stricter version of bezier scaling
+    BezierStrict,
+    /// RatioClamp which blends smoothly towards the dark length as the start length falls below
+    /// knee, where the ratio between the lengths stops being meaningful.
+    RatioBlend { knee: f32, dark: DarkLength },
+    /// Scale along a curve through the given (input length, output length) control points.
+    Curve {
+        points: Vec<(f32, f32)>,
+        #[serde(default)]
+        interpolation: Interpolation,
+    },
+    /// Gamut compression which rolls value off smoothly above knee instead of clipping it,
+    /// keeping hue and saturation. Scales by the ratio between the end and start lengths first
+    /// when ratio is set.
+    SoftValue {
+        knee: f32,
+        #[serde(default)]
+        ratio: bool,
+    },
+    /// SoftValue which also desaturates colors as they are rolled off, so over-bright folds fade
+    /// towards white instead of flattening at full saturation.
+    SoftSaturation {
+        knee: f32,
+        #[serde(default)]
+        ratio: bool,
+    },
+}
+
+/// The length a vector is scaled to when the start of a ratio is close to black.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub enum DarkLength {
+    /// Keep the length of the vector.
+    Keep,
+    /// Interpolate the length linearly through (start, end) and (1, 1).
+    Interpolate,
+    /// Raise the length to at least the given floor.
+    Floor(f32),
+}
+
+impl DarkLength {
+    /// The mode and floor arguments of RatioBlend in the shader library.
+    fn arguments(&self) -> (i32, f32) {
+        match *self {
+            DarkLength::Keep => (0, 0.0),
+            DarkLength::Interpolate => (1, 0.0),
+            DarkLength::Floor(x) => (2, x),
+        }
+    }
+}
+
+/// How the pull of a density shift fades between its center and its radius.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub enum Falloff {
+    /// Fade linearly from full strength at the center to nothing at the radius.
+    Linear,
+    /// Fade along a smoothstep, so the edge of the shifted region is seamless.
+    #[default]
+    Smooth,
+    /// Full strength everywhere within the radius, for a hard edge.
+    Constant,
+}
+
+impl Falloff {
+    /// The falloff argument of density_shift in the shader library.
+    fn argument(&self) -> i32 {
+        match *self {
+            Falloff::Linear => 0,
+            Falloff::Smooth => 1,
+            Falloff::Constant => 2,
+        }
+    }
+}
+
+/// Which colors a reflection moves.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub enum ReflectMode {
+    /// Fold the side of the plane the normal points to onto the other side.
+    #[default]
+    Fold,
+    /// Mirror colors on both sides of the plane.
+    Mirror,
+}
+
+impl ReflectMode {
+    /// The mode argument of point_reflect in the shader library.
+    fn argument(&self) -> i32 {
+        match *self {
+            ReflectMode::Fold => 0,
+            ReflectMode::Mirror => 1,
+        }
+    }
+}
+
+/// The angle a twist rotates a color by, in turns, so an angle of 0.5 moves hues to their
+/// opposite.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum TwistAngle {
+    /// Any float expression, such as a float input or a channel of an image.
+    Float(FloatData),
+    /// A curve through (channel, angle) control points, where the channel is read from the color
+    /// being twisted.
+    Curve {
+        channel: ColorProperties,
+        points: Vec<(f32, f32)>,
+        #[serde(default)]
+        interpolation: Interpolation,
+    },
+}
+
+impl Scale {
+    /// Cpu evaluation of the scaling function of the same name in the shader library.
+    pub fn evaluate(&self, in_vec: Vec4, start: f32, end: f32) -> Vec4 {
+        use filters::Scale::*;
+        match self {
+            Clamp => clamp(in_vec, start, end),
+            RatioClamp => ratio_clamp(in_vec, start, end),
+            BezierLoose => bezier_loose(in_vec, start, end),
+            BezierStrict => bezier_strict(in_vec, start, end),
+            RatioBlend { knee, dark } => {
+                let (mode, floor) = dark.arguments();
+                ratio_blend(in_vec, start, end, *knee, mode, floor)
+            }
+            Curve {
+                points,
+                interpolation,
+            } => curve::evaluate(&curve::segments(points, interpolation), in_vec),
+            SoftValue { knee, ratio } => soft_value(in_vec, start, end, *knee, *ratio),
+            SoftSaturation { knee, ratio } => soft_saturation(in_vec, start, end, *knee, *ratio),
+        }
+    }
+
+    /// The call to the scaling function with the given arguments, followed by the definition of
+    /// the function if it is generated instead of coming from the shader library.
+    pub fn get_shader(
+        &self,
+        path: &str,
+        names: &mut FunctionNames,
+        in_vec: &str,
+        start: &str,
+        end: &str,
+    ) -> Vec<String> {
+        use filters::Scale::*;
+        match self {
+            RatioBlend { knee, dark } => {
+                let (mode, floor) = dark.arguments();
+                vec![format!(
+                    "RatioBlend({}, {}, {}, {:?}, {}, {:?})",
+                    in_vec, start, end, knee, mode, floor
+                )]
+            }
+            Curve {
+                points,
+                interpolation,
+            } => {
+                let function = names.allocate_curve();
+                let segments = curve::segments(points, interpolation);
+                vec![
+                    format!("{}({}, {}, {})", function, in_vec, start, end),
+                    curve::get_shader(&function, path, &segments),
+                ]
+            }
+            SoftValue { knee, ratio } => vec![format!(
+                "SoftValue({}, {}, {}, {:?}, {})",
+                in_vec, start, end, knee, ratio
+            )],
+            SoftSaturation { knee, ratio } => vec![format!(
+                "SoftSaturation({}, {}, {}, {:?}, {})",
+                in_vec, start, end, knee, ratio
+            )],
+            x => vec![format!("{:?}({}, {}, {})", x, in_vec, start, end)],
+        }
+    }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        match *self {
+            Scale::RatioBlend { knee, ref dark } => {
+                if !(0.0..=1.0).contains(&knee) {
+                    validation.error(path, format!("The knee {:?} must be between 0 and 1", knee));
+                }
+                if let DarkLength::Floor(floor) = *dark {
+                    if !(0.0..=1.0).contains(&floor) {
+                        validation
+                            .error(path, format!("The floor {:?} must be between 0 and 1", floor));
+                    }
+                }
+            }
+            Scale::SoftValue { knee, .. } | Scale::SoftSaturation { knee, .. }
+                if !(0.0..=1.0).contains(&knee) =>
+            {
+                validation.error(path, format!("The knee {:?} must be between 0 and 1", knee));
+            }
+            Scale::Curve { ref points, .. } => {
+                curve::validate(points, true, &format!("{}.points", path), validation)
+            }
+            _ => {}
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -21,50 +226,301 @@ pub enum Compute {
         operations: Vec<Compute>,
         sum_type: Summation,
     },
+    /// Rotate colors by the rotation from start_point to end_point, weighted by their distance to
+    /// start_point. Colors rotate about black unless a center is given to pivot around.
     Rotation {
         start_point: Geom0D,
         end_point: Geom0D,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center: Option<Geom0D>,
         source: String,
         rescale: Scale,
     },
+    /// Shift colors by the vector from start_point to end_point, weighted by their distance to
+    /// start_point like a rotation.
+    Translation {
+        start_point: Geom0D,
+        end_point: Geom0D,
+        source: String,
+        rescale: Scale,
+    },
+    /// Contract colors within radius of center towards it, or expand them away from it for a
+    /// negative strength. A strength of 1 pulls colors at the center all the way onto it.
+    DensityShift {
+        center: Geom0D,
+        strength: FloatData,
+        radius: FloatData,
+        #[serde(default)]
+        falloff: Falloff,
+        source: String,
+    },
+    /// Reflect colors across the plane through point which is perpendicular to the direction of
+    /// normal, weighted by their distance to point.
+    Reflect {
+        point: Geom0D,
+        normal: Geom0D,
+        #[serde(default)]
+        mode: ReflectMode,
+        source: String,
+    },
+    /// Rotate colors about the grey axis by an angle which may vary from color to color, shifting
+    /// their hue. A twist moves every color, so it has no weight and overrides the weighted
+    /// operations it is summed with, like a nested compute.
+    Twist { angle: TwistAngle, source: String },
 }
 
 impl Compute {
     pub fn is_compute(&self) -> bool {
         use filters::Compute::Compute;
-        if let &Compute { .. } = self {
-            true
-        } else {
-            false
+        matches!(self, Compute { .. })
+    }
+
+    /// The name of the kind of compute object, as written in descriptors.
+    pub fn kind(&self) -> &'static str {
+        use filters::Compute::*;
+        match self {
+            Compute { .. } => "Compute",
+            Rotation { .. } => "Rotation",
+            Translation { .. } => "Translation",
+            DensityShift { .. } => "DensityShift",
+            Reflect { .. } => "Reflect",
+            Twist { .. } => "Twist",
         }
     }
 
     pub fn get_file(&self) -> String {
         use filters::Compute::*;
         match self {
-            &Compute { ref name, .. } => name.clone(),
-            &Rotation { ref source, .. } => source.clone(),
+            Compute { name, .. } => name.clone(),
+            Rotation { source, .. }
+            | Translation { source, .. }
+            | DensityShift { source, .. }
+            | Reflect { source, .. }
+            | Twist { source, .. } => source.clone(),
         }
     }
 
     pub fn get_required_sources(&self, graph: &mut DataDependencyGraph) {
         use filters::Compute::*;
         match self {
-            &Compute { ref operations, .. } => {
+            Compute { operations, .. } => {
                 for op in operations {
                     op.get_required_sources(graph)
                 }
             }
-            &Rotation {
-                ref start_point,
-                ref end_point,
-                ref source,
+            Rotation {
+                start_point,
+                end_point,
+                center,
+                source,
+                ..
+            } => {
+                start_point.get_required_sources(graph);
+                end_point.get_required_sources(graph);
+                if let Some(center) = center {
+                    center.get_required_sources(graph);
+                }
+                graph.require_image(source.clone());
+            }
+            Translation {
+                start_point,
+                end_point,
+                source,
                 ..
             } => {
                 start_point.get_required_sources(graph);
                 end_point.get_required_sources(graph);
                 graph.require_image(source.clone());
             }
+            DensityShift {
+                center,
+                strength,
+                radius,
+                source,
+                ..
+            } => {
+                center.get_required_sources(graph);
+                strength.get_required_sources(graph);
+                radius.get_required_sources(graph);
+                graph.require_image(source.clone());
+            }
+            Reflect {
+                point,
+                normal,
+                source,
+                ..
+            } => {
+                point.get_required_sources(graph);
+                normal.get_required_sources(graph);
+                graph.require_image(source.clone());
+            }
+            Twist { angle, source } => {
+                match angle {
+                    TwistAngle::Float(x) => x.get_required_sources(graph),
+                    TwistAngle::Curve { channel, .. } => source_color(source)
+                        .get_required_channel_sources(graph, *channel),
+                }
+                graph.require_image(source.clone());
+            }
+        }
+    }
+
+    /// Report every problem with this compute object and the objects it is built from.
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        use filters::Compute::*;
+        match self {
+            Compute {
+                name, operations, ..
+            } => {
+                validation.check_compute_name(path, name);
+                if operations.is_empty() {
+                    validation.error(path, "A compute object needs at least one operation");
+                }
+                // A twist has no weight, so it would silently override everything beside it.
+                let twists = operations
+                    .iter()
+                    .filter(|op| matches!(op, Twist { .. }))
+                    .count();
+                if twists > 0 && twists < operations.len() {
+                    validation.error(
+                        path,
+                        "A Twist would override every other operation of its compute object, so it can only be combined with other Twists. Move it into a stage of its own",
+                    );
+                }
+                for (i, op) in operations.iter().enumerate() {
+                    op.validate(&format!("{}.operations[{}]", path, i), validation);
+                }
+            }
+            Rotation {
+                start_point,
+                end_point,
+                center,
+                rescale,
+                ..
+            } => {
+                start_point.validate(&format!("{}.start_point", path), validation);
+                end_point.validate(&format!("{}.end_point", path), validation);
+                if let Some(center) = center {
+                    center.validate(&format!("{}.center", path), validation);
+                }
+                rescale.validate(&format!("{}.rescale", path), validation);
+            }
+            Translation {
+                start_point,
+                end_point,
+                rescale,
+                ..
+            } => {
+                start_point.validate(&format!("{}.start_point", path), validation);
+                end_point.validate(&format!("{}.end_point", path), validation);
+                rescale.validate(&format!("{}.rescale", path), validation);
+            }
+            DensityShift {
+                center,
+                strength,
+                radius,
+                ..
+            } => {
+                center.validate(&format!("{}.center", path), validation);
+                strength.validate(&format!("{}.strength", path), validation);
+                radius.validate(&format!("{}.radius", path), validation);
+                if let FloatData::Constant(x) = *radius {
+                    if x <= 0.0 {
+                        validation.error(
+                            &format!("{}.radius", path),
+                            format!("The radius {:?} must be greater than 0", x),
+                        );
+                    }
+                }
+            }
+            Reflect { point, normal, .. } => {
+                point.validate(&format!("{}.point", path), validation);
+                normal.validate(&format!("{}.normal", path), validation);
+            }
+            Twist { angle, .. } => match angle {
+                TwistAngle::Float(x) => x.validate(&format!("{}.angle", path), validation),
+                TwistAngle::Curve { points, .. } => {
+                    curve::validate(points, false, &format!("{}.angle.points", path), validation)
+                }
+            },
+        }
+    }
+
+    /// Cpu evaluation of this compute object at a single pixel. Mirrors the shader generated by
+    /// get_shader, returning null if nothing contributed to the sum.
+    pub fn evaluate(&self, pixel: &Pixel) -> Vec4 {
+        use filters::Compute::*;
+        match self {
+            Compute { operations, .. } => {
+                let mut sum = InvWeightedSum::default();
+                for op in operations {
+                    sum.add(op.evaluate(pixel));
+                }
+                sum.total()
+            }
+            Rotation {
+                start_point,
+                end_point,
+                center: None,
+                source,
+                rescale,
+            } => evaluate_operation(pixel, start_point, end_point, source, rescale, point_point),
+            Rotation {
+                start_point,
+                end_point,
+                center: Some(center),
+                source,
+                rescale,
+            } => {
+                let center = center.evaluate(pixel);
+                evaluate_operation(pixel, start_point, end_point, source, rescale, |x, start, end| {
+                    point_point_centered(x, start, end, center)
+                })
+            }
+            DensityShift {
+                center,
+                strength,
+                radius,
+                falloff,
+                source,
+            } => density_shift(
+                pixel.image(source).point,
+                center.evaluate(pixel),
+                strength.evaluate(pixel),
+                radius.evaluate(pixel),
+                falloff.argument(),
+            ),
+            Reflect {
+                point,
+                normal,
+                mode,
+                source,
+            } => point_reflect(
+                pixel.image(source).point,
+                point.evaluate(pixel),
+                normal.evaluate(pixel),
+                mode.argument(),
+            ),
+            Twist { angle, source } => {
+                let turns = match angle {
+                    TwistAngle::Float(x) => x.evaluate(pixel),
+                    TwistAngle::Curve {
+                        channel,
+                        points,
+                        interpolation,
+                    } => curve::value(
+                        &curve::segments(points, interpolation),
+                        source_color(source).evaluate_channel(pixel, *channel),
+                    ),
+                };
+                point_twist(pixel.image(source).point, turns)
+            }
+            Translation {
+                start_point,
+                end_point,
+                source,
+                rescale,
+            } => evaluate_operation(pixel, start_point, end_point, source, rescale, point_translate),
         }
     }
 
@@ -72,16 +528,20 @@ impl Compute {
     // The first string is the line to call this compute shader
     // The rest of the items are fully written out definitions for required compute shaders.
     // Print these in inverse order.
-    pub fn get_shader(&self) -> Vec<String> {
+    // The path names this node in the descriptor and is written into the shader as a comment so
+    // errors in the generated code can be traced back to the node which produced them. Names
+    // hands out the function name of every compute object in the shader.
+    pub fn get_shader(&self, path: &str, names: &mut FunctionNames) -> Vec<String> {
         use filters::Compute::*;
         match self
         {
-            &Compute  { ref name, ref operations, sum_type:ref _sum_type } =>
+            Compute  { name, operations, sum_type:_sum_type } =>
             {
                 let mut params = DataDependencyGraph::new();
                 self.get_required_sources(&mut params);
-                let mut call_line = format!("{name}(", name=name);
-                let mut function_def = format!("vec4 {name}(", name=name);
+                let function = names.allocate(name);
+                let mut call_line = format!("{function}(", function=function);
+                let mut function_def = format!("// {path}\nvec4 {function}(", path=path, function=function);
                 let mut initialization = String::new();
                 for (i, (source, usages)) in params.iter().enumerate()
                 {
@@ -91,43 +551,48 @@ impl Compute {
                     }
 
                     use data::{DataSourceKind::*};
+                    let source_name = mangle(&source.name);
                     function_def = format!(
                         "{function_def}{param}",
                         function_def=function_def,
                         param=match source.kind {
-                            Color => format!("vec3 col_{}_rgb", source.name),
-                            Image => format!("vec3 img_{}_rgb", source.name),
-                            Float => format!("float float_{}", source.name),
+                            Color => format!("vec3 col_{}__rgb", source_name),
+                            Image | Stage => format!("vec3 img_{}__rgb", source_name),
+                            Float => format!("float float_{}", source_name),
                         }
                     );
                     call_line = format!(
                         "{call_line}{param}",
                         call_line=call_line,
                         param=match source.kind {
-                            Color => format!("col_{}_rgb", source.name),
-                            Image => format!("img_{}_rgb", source.name),
-                            Float => format!("float_{}", source.name),
+                            Color => format!("col_{}__rgb", source_name),
+                            Image | Stage => format!("img_{}__rgb", source_name),
+                            Float => format!("float_{}", source_name),
                         }
                     );
 
                     let kind_string = if source.kind == Color { "col" } else { "img" };
+                    if usages.require_alpha {
+                        function_def += &format!(", float {}_{}__a", kind_string, source_name);
+                        call_line += &format!(", {}_{}__a", kind_string, source_name);
+                    }
                     if usages.require_hsv || usages.require_point {
-                        initialization = format!("{initialization}\nvec3 {kind_string}_{name}_hsv = rgb2hsv({kind_string}_{name}_rgb);",
+                        initialization = format!("{initialization}\nvec3 {kind_string}_{name}__hsv = rgb2hsv({kind_string}_{name}__rgb);",
                         initialization=initialization,
                         kind_string=kind_string,
-                        name=source.name);
+                        name=source_name);
                     }
                     if usages.require_point {
-                        initialization = format!("{initialization}\nvec4 {kind_string}_{name} = hsv2half_spherical({kind_string}_{name}_hsv);",
+                        initialization = format!("{initialization}\nvec4 {kind_string}_{name} = hsv2half_spherical({kind_string}_{name}__hsv);",
                         initialization=initialization,
                         kind_string=kind_string,
-                        name=source.name);
+                        name=source_name);
                     }
                 }
                 function_def += ")\n{";
                 function_def += &initialization;
                 function_def += "
-  float total_inv_weight = 0;
+  float total_inv_weight = 0.0;
   vec3 total_inv_weight_vecs = vec3(0);
   int num_zeros = 0;
   vec3 total_zeros = vec3(0);
@@ -136,51 +601,57 @@ impl Compute {
                 call_line += ")";
                 let mut inner_compute_functions = Vec::new();
 
-                for op in operations
+                for (i, op) in operations.iter().enumerate()
                 {
                     // Get the returned shaders
-                    let mut returned_shaders : Vec<String> = op.get_shader().iter().cloned().collect();
+                    let op_path = format!("{}.operations[{}]", path, i);
+                    let mut returned_shaders : Vec<String> = op.get_shader(&op_path, names);
                     let first_line = returned_shaders.remove(0);
 
                     match op
                     {
-                        &Compute { name: ref inner_name, .. } =>
+                        Compute { .. } =>
                         {
+                            // The call line starts with the name of the inner function.
+                            let inner_name = &first_line[..first_line.find('(').unwrap_or(0)];
                             function_def += &format!(
 "
-vec4 {name}_rot = {function_call};
-if ({name}_rot.w > -0.5)
+// {path}
+vec4 {name}__rot = {function_call};
+if ({name}__rot.w > -0.5)
 {{
-    if ({name}_rot.w < Epsilon)
+    if ({name}__rot.w < Epsilon)
     {{
         num_zeros += 1;
-        total_zeros += {name}_rot.xyz;
+        total_zeros += {name}__rot.xyz;
     }}
     else
     {{
-        total_inv_weight += 1 / {name}_rot.w;
-        total_inv_weight_vecs += 1 / {name}_rot.w * {name}_rot.xyz;
+        total_inv_weight += 1.0 / {name}__rot.w;
+        total_inv_weight_vecs += 1.0 / {name}__rot.w * {name}__rot.xyz;
     }}
 }}",
-                                name=inner_name, function_call=first_line);
+                                path=op_path, name=inner_name, function_call=first_line);
                             inner_compute_functions.append(&mut returned_shaders);
                         },
-                        &Rotation { .. } =>
+                        _ =>
                         {
                             function_def += &first_line;
+                            inner_compute_functions.append(&mut returned_shaders);
                         }
                     }
                 }
 
+                function_def += &format!("\n// {}", path);
                 function_def +=
 "
   if (num_zeros > 0)
   {
-    return vec4(total_zeros / num_zeros, 0);
+    return vec4(total_zeros / float(num_zeros), 0);
   }
   else if (total_inv_weight > Epsilon)
   {
-      return vec4((1 / total_inv_weight) * total_inv_weight_vecs, 0);
+      return vec4((1.0 / total_inv_weight) * total_inv_weight_vecs, 0);
   }
   else
   {
@@ -193,31 +664,153 @@ if ({name}_rot.w > -0.5)
                 inner_compute_functions
 
             },
-            &Rotation { ref start_point, ref end_point, ref source, ref rescale } =>
+            Rotation { start_point, end_point, center, source, rescale } =>
             {
-                vec![format!(
+                let mapping = match center {
+                    None => format!("point_point(img_{}, _rot_start_, _rot_end_)", mangle(source)),
+                    Some(center) => format!(
+                        "point_point_centered(img_{}, _rot_start_, _rot_end_, {})",
+                        mangle(source), center.get_shader()),
+                };
+                operation_shader(path, names, start_point, end_point, source, rescale, &mapping)
+            }
+            Translation { start_point, end_point, source, rescale } =>
+            {
+                let mapping = format!("point_translate(img_{}, _rot_start_, _rot_end_)", mangle(source));
+                operation_shader(path, names, start_point, end_point, source, rescale, &mapping)
+            }
+            DensityShift { center, strength, radius, falloff, source } =>
+            {
+                let result = format!(
+                    "density_shift(img_{}, _rot_start_, {}, {}, {})",
+                    mangle(source), strength.reference_string(), radius.reference_string(), falloff.argument());
+                vec![sum_shader(path, &[("_rot_start_", center)], source, &result)]
+            }
+            Reflect { point, normal, mode, source } =>
+            {
+                let result = format!(
+                    "point_reflect(img_{}, _rot_start_, _rot_end_, {})",
+                    mangle(source), mode.argument());
+                vec![sum_shader(path, &[("_rot_start_", point), ("_rot_end_", normal)], source, &result)]
+            }
+            Twist { angle, source } =>
+            {
+                let (turns, definitions) = match angle {
+                    TwistAngle::Float(x) => (x.reference_string(), Vec::new()),
+                    TwistAngle::Curve { channel, points, interpolation } =>
+                    {
+                        let function = names.allocate_curve();
+                        let definition = curve::get_value_shader(
+                            &function, &format!("{}.angle", path), &curve::segments(points, interpolation));
+                        let call = format!(
+                            "{}({})", function, source_color(source).channel_reference_string(*channel));
+                        (call, vec![definition])
+                    }
+                };
+                let result = format!("point_twist(img_{}, {})", mangle(source), turns);
+                let mut shaders = vec![sum_shader(path, &[], source, &result)];
+                shaders.extend(definitions);
+                shaders
+            }
+        }
+    }
+}
+
+/// The color of the source image an operation reads.
+fn source_color(source: &str) -> ColorData {
+    ColorData::ImageRef {
+        image_source: source.to_owned(),
+    }
+}
+
+/// Cpu evaluation of an operation which maps colors of the source image with the given mapping,
+/// called with the color and the start and end points. Mirrors operation_shader.
+fn evaluate_operation<F>(
+    pixel: &Pixel,
+    start_point: &Geom0D,
+    end_point: &Geom0D,
+    source: &str,
+    rescale: &Scale,
+    mapping: F,
+) -> Vec4
+where
+    F: Fn(Vec4, Vec4, Vec4) -> Vec4,
+{
+    let start = start_point.evaluate(pixel);
+    let end = end_point.evaluate(pixel);
+    if start.is_null() || end.is_null() {
+        return Vec4::null();
+    }
+    rescale.evaluate(
+        mapping(pixel.image(source).point, start, end),
+        start.xyz().length(),
+        end.xyz().length(),
+    )
+}
+
+/// The shader for an operation which maps colors of the source image with the mapping call, an
+/// expression reading the start and end points from `_rot_start_` and `_rot_end_`, and rescales
+/// the result. The first string is the code added to the compute function and the rest are
+/// definitions it needs.
+fn operation_shader(
+    path: &str,
+    names: &mut FunctionNames,
+    start_point: &Geom0D,
+    end_point: &Geom0D,
+    source: &str,
+    rescale: &Scale,
+    mapping: &str,
+) -> Vec<String> {
+    let mut scale_shaders = rescale.get_shader(
+        &format!("{}.rescale", path),
+        names,
+        mapping,
+        "length(_rot_start_.xyz)",
+        "length(_rot_end_.xyz)",
+    );
+    let scale_call = scale_shaders.remove(0);
+    let points = [("_rot_start_", start_point), ("_rot_end_", end_point)];
+    let mut shaders = vec![sum_shader(path, &points, source, &scale_call)];
+    shaders.append(&mut scale_shaders);
+    shaders
+}
+
+/// The code adding the result of an operation to the sum of the enclosing compute function. Each
+/// point is first stored in the given local of the compute function, and the result expression
+/// is only evaluated if none of them are null.
+fn sum_shader(path: &str, points: &[(&str, &Geom0D)], source: &str, result: &str) -> String {
+    let mut assignments = String::new();
+    for (local, point) in points {
+        assignments += &format!("\n{} = {};", local, point.get_shader());
+    }
+    let condition = if points.is_empty() {
+        String::from("true")
+    } else {
+        points
+            .iter()
+            .map(|(local, _)| format!("{}.w > -0.5", local))
+            .collect::<Vec<_>>()
+            .join(" && ")
+    };
+    format!(
 "
-_rot_start_ = {start};
-_rot_end_ = {end};
-if (_rot_start_.w > -0.5 && _rot_end_.w > -0.5)
+// {path}{assignments}
+if ({condition})
 {{
-    vec4 {source}_rot = {rescale:?}(point_point(img_{source}, _rot_start_, _rot_end_), length(_rot_start_.xyz), length(_rot_end_.xyz));
-    if ({source}_rot.w > -0.5)
+    vec4 {source}__rot = {result};
+    if ({source}__rot.w > -0.5)
     {{
-        if ({source}_rot.w < Epsilon)
+        if ({source}__rot.w < Epsilon)
         {{
             num_zeros += 1;
-            total_zeros += {source}_rot.xyz;
+            total_zeros += {source}__rot.xyz;
         }}
         else
         {{
-            total_inv_weight += 1 / {source}_rot.w;
-            total_inv_weight_vecs += 1 / {source}_rot.w * {source}_rot.xyz;
+            total_inv_weight += 1.0 / {source}__rot.w;
+            total_inv_weight_vecs += 1.0 / {source}__rot.w * {source}__rot.xyz;
         }}
     }}
 }}",
-                start=start_point.get_shader(), end=end_point.get_shader(), rescale=rescale, source=source)]
-            }
-        }
-    }
+    path=path, assignments=assignments, condition=condition, result=result, source=mangle(source))
 }
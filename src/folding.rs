@@ -1,7 +1,17 @@
-use data::DataSourceKind;
+use color::Color;
+use data::{Alpha, AlphaSource, DataSource, DataSourceKind, Input};
 use dependency::DataDependencyGraph;
+use error::{ColorfoldError, Result};
+use evaluation::{folded_to_rgb, Pixel, Sample, Vec3, Vec4};
+use glsl::{json_string, mangle, validate_isf, FunctionNames, ShaderError, LIBRARY};
+use lut::Lut;
+use validation::{Validation, ValidationError};
 use filters::Compute as ComputeShader;
-use std::path::PathBuf;
+use image::{Rgba, RgbaImage};
+use imaging::Image;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
@@ -11,137 +21,797 @@ use serde::{Deserialize, Serialize};
  #[derive(Serialize, Deserialize)]
 pub struct FoldingMachine {
     stages: Vec<ComputeShader>,
+    /// Image files bound to image sources by name. Relative locations are resolved against the
+    /// directory holding the descriptor.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<Image>,
+    /// Labels, defaults and ranges for the inputs of the generated shader.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<Input>,
+    #[serde(default, skip_serializing_if = "IsfHeader::is_default")]
+    header: IsfHeader,
+    #[serde(default, skip_serializing_if = "Alpha::is_default")]
+    alpha: Alpha,
     location: Option<PathBuf>
 }
 
+/// The metadata written at the top of the generated ISF shader.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct IsfHeader {
+    description: String,
+    credit: String,
+    /// Defaults to Generator, or Filter when the shader is a filter.
+    categories: Option<Vec<String>>,
+    isf_version: String,
+    /// Image source bound to the standard inputImage, so VDMX applies the shader as an effect on a
+    /// layer instead of treating it as a generator with an image input.
+    filter: Option<String>,
+}
+
+impl Default for IsfHeader {
+    fn default() -> Self {
+        IsfHeader {
+            description: String::from("An autogenerated shader by colorfold"),
+            credit: String::from("generated by Colorfold"),
+            categories: None,
+            isf_version: String::from("2.0"),
+            filter: None,
+        }
+    }
+}
+
+impl IsfHeader {
+    fn is_default(&self) -> bool {
+        *self == IsfHeader::default()
+    }
+
+    /// The start of the json header, up to the opening of the INPUTS list.
+    fn opening(&self) -> String {
+        let categories = match (&self.categories, &self.filter) {
+            (Some(categories), _) => categories.clone(),
+            (None, Some(_)) => vec![String::from("Filter")],
+            (None, None) => vec![String::from("Generator")],
+        };
+        let categories: Vec<String> = categories.iter().map(|x| json_string(x)).collect();
+        format!(
+            "/*{{
+    \"DESCRIPTION\": {description},
+    \"CREDIT\": {credit},
+    \"ISFVSN\": {version},
+    \"CATEGORIES\": [
+    {categories}
+    ],
+    \"INPUTS\": [
+",
+            description = json_string(&self.description),
+            credit = json_string(&self.credit),
+            version = json_string(&self.isf_version),
+            categories = categories.join(",\n    ")
+        )
+    }
+
+    /// The name of the ISF image input an image source is read from.
+    fn image_input(&self, source: &DataSource) -> String {
+        if source.kind == DataSourceKind::Image && self.filter.as_ref() == Some(&source.name) {
+            String::from("inputImage")
+        } else {
+            format!("{}__img", mangle(&source.name))
+        }
+    }
+}
+
 impl FoldingMachine {
+    /// Load a descriptor from a file. Files ending in .json are read as json and everything
+    /// else as ron.
+    pub fn load(file: &Path) -> Result<FoldingMachine> {
+        let json_ext = Some(OsStr::new("json"));
+        let input = std::fs::read_to_string(file).map_err(|e| ColorfoldError::io(file, e))?;
+        let folder: FoldingMachine = if file.extension() == json_ext {
+            FoldingMachine::from_json(file, &input)?
+        } else {
+            FoldingMachine::from_ron(file, &input)?
+        };
+        Ok(folder.with_location(file.to_path_buf()))
+    }
+
+    /// Parse a descriptor in json form, where file is only used to report errors.
+    pub fn from_json(file: &Path, input: &str) -> Result<FoldingMachine> {
+        serde_json::from_str(input).map_err(|e| ColorfoldError::json(file, &e))
+    }
+
+    /// Parse a descriptor in ron form, where file is only used to report errors.
+    pub fn from_ron(file: &Path, input: &str) -> Result<FoldingMachine> {
+        let mut deserializer = ron::de::Deserializer::from_str(input)
+            .map_err(|e| ColorfoldError::ron(file, e, input, 0))?;
+        let result = FoldingMachine::deserialize(&mut deserializer)
+            .and_then(|folder| deserializer.end().map(|_| folder));
+        // Errors raised by serde itself carry no position, so report them where the parser
+        // stopped.
+        let offset = input.len() - deserializer.remainder().len();
+        result.map_err(|e| ColorfoldError::ron(file, e, input, offset))
+    }
+
     pub fn with_location(mut self, p: PathBuf) -> Self {
         self.location = Some(p);
         self
     }
 
-    pub fn vdmx_shader(&mut self, stage: usize, save: bool) -> std::io::Result<String> {
-        let stage = &self.stages[stage];
+    /// Check the whole machine for problems which would produce a broken shader, reporting all of
+    /// them at once.
+    pub fn validate(&self) -> std::result::Result<(), Vec<ValidationError>> {
+        let mut validation = Validation::new();
+        if self.stages.is_empty() {
+            validation.error("stages", "A folding machine needs at least one stage");
+        }
+        for (i, stage) in self.stages.iter().enumerate() {
+            let path = format!("stages[{}]", i);
+            stage.validate(&path, &mut validation);
+            validation.check_stage_name(&path, &stage.get_file());
+            let target = DataSource {
+                name: stage.get_file(),
+                kind: DataSourceKind::Stage,
+            };
+            validation.check_source(&path, &target);
+            if !stage.is_compute() {
+                let message = format!("A stage must be a Compute, not a bare {}", stage.kind());
+                validation.error(&path, message);
+            } else {
+                match self.stage_sources(i) {
+                    Ok(graph) => {
+                        for source in graph.keys() {
+                            validation.check_source(&path, source);
+                        }
+                    }
+                    Err(e) => validation.error(&path, e.to_string()),
+                }
+            }
+        }
+        self.alpha.validate("alpha", &mut validation);
+        self.validate_inputs(&mut validation);
+        if let Some(ref filter) = self.header.filter {
+            let source = DataSource {
+                name: filter.clone(),
+                kind: DataSourceKind::Image,
+            };
+            if self.external_sources().is_ok_and(|x| !x.contains(&source)) {
+                let message = format!("The machine does not read an image source named {:?}", filter);
+                validation.error("header.filter", message);
+            }
+        }
+        validation.into_result()
+    }
+
+    /// Check that every declared input describes a source the machine reads from outside of it.
+    fn validate_inputs(&self, validation: &mut Validation) {
+        // Stage problems are already reported and leave nothing to check the inputs against.
+        let sources = match self.external_sources() {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        let names = self.stage_names();
+        for (i, input) in self.inputs.iter().enumerate() {
+            let path = format!("inputs[{}]", i);
+            input.validate(&path, validation);
+            let source = input.source();
+            if let Some(first) = self.inputs[..i].iter().position(|x| x.source() == source) {
+                let message = format!(
+                    "The {} input {:?} is already declared by inputs[{}]",
+                    source.kind, source.name, first
+                );
+                validation.error(&path, message);
+            } else if !sources.contains(&source) {
+                let used_as = sources.iter().find(|x| x.name == source.name);
+                let message = match used_as {
+                    Some(used) => format!(
+                        "The machine uses {:?} as a {} source, not a {} source",
+                        source.name, used.kind, source.kind
+                    ),
+                    None if source.kind == DataSourceKind::Image && names.contains(&source.name) => {
+                        format!(
+                            "{:?} is a stage, which is read from its pass target rather than an input",
+                            source.name
+                        )
+                    }
+                    None => format!(
+                        "The machine does not use a {} source named {:?}",
+                        source.kind, source.name
+                    ),
+                };
+                validation.error(&path, message);
+            }
+        }
+    }
+
+    /// All images declared by this descriptor keyed by name with their locations resolved. The
+    /// images are not loaded until they are used.
+    pub fn images(&self) -> HashMap<String, Image> {
+        let directory = self.location.as_ref().and_then(|x| x.parent());
+        self.images
+            .iter()
+            .map(|image| {
+                let location = match directory {
+                    Some(dir) => dir.join(&image.location).to_string_lossy().into_owned(),
+                    None => image.location.clone(),
+                };
+                (image.id.clone(), Image::new(image.id.clone(), location))
+            })
+            .collect()
+    }
+
+    pub fn get_required_sources(&self, stage: usize, graph: &mut DataDependencyGraph) {
+        if let Some(stage) = self.stages.get(stage) {
+            stage.get_required_sources(graph)
+        }
+    }
+
+    fn stage_names(&self) -> Vec<String> {
+        self.stages.iter().map(|x| x.get_file()).collect()
+    }
+
+    /// Every data source read by the pass of a stage, including those its output alpha is read
+    /// from, with references to stages resolved.
+    fn stage_graph(&self, stage: usize, names: &[String]) -> DataDependencyGraph {
+        let mut graph = DataDependencyGraph::new();
+        self.get_required_sources(stage, &mut graph);
+        self.alpha.get_required_sources(&mut graph);
+        graph.resolve_stages(names);
+        graph
+    }
+
+    /// Every data source a stage requires. Image sources named after a stage of this machine are
+    /// references to the output of that stage, which must come earlier in the machine.
+    pub fn stage_sources(&self, stage: usize) -> Result<DataDependencyGraph> {
+        let names = self.stage_names();
+        let graph = self.stage_graph(stage, &names);
 
-        // ========================================================================================
-        // == Load images into memory and create the color loading string for the shader         ==
-        // ========================================================================================
+        for source in graph.keys() {
+            if source.kind != DataSourceKind::Stage {
+                continue;
+            }
+            let referenced = names.iter().position(|x| *x == source.name).unwrap_or(0);
+            if referenced < stage {
+                continue;
+            }
+            let message = match self.stage_cycle(referenced, stage) {
+                Some(cycle) => {
+                    let mut cycle: Vec<&str> = cycle.iter().map(|&x| names[x].as_str()).collect();
+                    cycle.insert(0, &names[stage]);
+                    format!("Stages form a cycle: {}", cycle.join(" -> "))
+                }
+                None => format!(
+                    "Stage {} reads the output of stage {} which is rendered after it",
+                    names[stage], source.name
+                ),
+            };
+            return Err(ColorfoldError::semantic(message));
+        }
+        Ok(graph)
+    }
+
+    /// A chain of stage references leading from one stage back to another, if there is one.
+    fn stage_cycle(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let names = self.stage_names();
+        let mut visited = vec![false; self.stages.len()];
+        let mut chains = vec![vec![from]];
+        while let Some(chain) = chains.pop() {
+            let current = chain[chain.len() - 1];
+            if current == to {
+                return Some(chain);
+            }
+            if visited[current] {
+                continue;
+            }
+            visited[current] = true;
+            let graph = self.stage_graph(current, &names);
+            for source in graph.keys().filter(|x| x.kind == DataSourceKind::Stage) {
+                if let Some(next) = names.iter().position(|x| *x == source.name) {
+                    let mut next_chain = chain.clone();
+                    next_chain.push(next);
+                    chains.push(next_chain);
+                }
+            }
+        }
+        None
+    }
+
+    /// Every data source required by the machine from outside of it, in no particular order.
+    pub fn external_sources(&self) -> Result<Vec<DataSource>> {
+        let mut sources: Vec<DataSource> = Vec::new();
+        for stage in 0..self.stages.len() {
+            for source in self.stage_sources(stage)?.keys() {
+                if source.kind != DataSourceKind::Stage && !sources.contains(source) {
+                    sources.push(source.clone());
+                }
+            }
+        }
+        Ok(sources)
+    }
+
+    /// Generate the ISF shader for every stage of this machine. A machine with several stages is
+    /// rendered in one pass per stage, each of which renders into a target named after its stage
+    /// so that later stages can read it like any other image source.
+    pub fn vdmx_shader(&mut self, save: bool) -> Result<String> {
+        self.validate().map_err(ColorfoldError::Invalid)?;
+        let multi_pass = self.stages.len() > 1;
+        let targets = self.stage_names();
+
+        let mut shader_heading = self.header.opening();
+        let mut inputs: Vec<DataSource> = Vec::new();
         let mut entry_string = String::from(
             "void main()
 {
 ",
         );
-        let mut shader_heading = String::from(
-            "/".to_owned()
-                + "*{
-    \"DESCRIPTION\": \"An autogenerated shader by colorfold\",
-    \"CREDIT\": \"generated by Colorfold\",
-    \"ISFVSN\": \"2.0\",
-    \"CATEGORIES\": [
-    \"Generator\"
-    ],
-    \"INPUTS\": [
-",
-        );
+        let mut compute_shaders = Vec::new();
+        let mut function_names = FunctionNames::new();
+        // The alpha is computed in main, which needs its own hsv vectors for the channels it reads.
+        let mut alpha_sources = DataDependencyGraph::new();
+        self.alpha.get_required_sources(&mut alpha_sources);
+        alpha_sources.resolve_stages(&targets);
 
-        let mut img_sources = DataDependencyGraph::new();
-        stage.get_required_sources(&mut img_sources);
-        // Gather all images into their data arrays and check that all images have correct size.
-        for (i, source) in img_sources.keys().enumerate() {
-            if i > 0 {
-                shader_heading.push_str(",\n");
-            }
+        for (stage_index, stage) in self.stages.iter().enumerate() {
+            // ====================================================================================
+            // == Declare the inputs of this stage and create the color loading string           ==
+            // ====================================================================================
+            let mut stage_string = String::new();
+            let img_sources = self.stage_sources(stage_index)?;
+            for (source, usages) in img_sources.iter() {
+                // Earlier stages are read from their pass targets instead of an input.
+                if source.kind != DataSourceKind::Stage && !inputs.contains(source) {
+                    if !inputs.is_empty() {
+                        shader_heading.push_str(",\n");
+                    }
+                    let input = self.inputs.iter().find(|x| x.source() == *source);
+                    shader_heading += &input_declaration(source, input, &self.header);
+                    inputs.push(source.clone());
+                }
 
-            match source.kind {
-                DataSourceKind::Color => {
-                    shader_heading += &format!(
-                            "
-            {{
-                \"NAME\" : \"col_{name}_rgba\",
-                \"LABEL\" : \"Color {name}\",
-                \"TYPE\" : \"color\"
-            }}",
-                        name = source.name
-                    );
-                    entry_string.push_str(
-                        format!(
-                            "
-      vec3 col_{name}_rgb = col_{name}_rgba.xyz;",
-                            name = source.name
-                        )
-                        .as_str(),
-                    );
-                },
-                DataSourceKind::Float => {
-                    shader_heading += &format!(
+                match source.kind {
+                    DataSourceKind::Color => {
+                        stage_string.push_str(
+                            format!(
+                                "
+      vec3 col_{name}__rgb = col_{name}__rgba.xyz;",
+                                name = mangle(&source.name)
+                            )
+                            .as_str(),
+                        );
+                        if usages.require_alpha {
+                            stage_string += &format!(
+                                "
+      float col_{name}__a = col_{name}__rgba.a;",
+                                name = mangle(&source.name)
+                            );
+                        }
+                    },
+                    DataSourceKind::Float => {},
+                    DataSourceKind::Image | DataSourceKind::Stage => {
+                        let name = mangle(&source.name);
+                        let image = self.header.image_input(source);
+                        if usages.require_alpha || self.alpha.premultiplied {
+                            stage_string += &format!(
+                                "
+      float img_{name}__a = IMG_THIS_PIXEL({image}).a;",
+                                name = name,
+                                image = image
+                            );
+                        }
+                        // Create the shader string which loads the hsv vector for this image at a given pixel
+                        let unpremultiply = if self.alpha.premultiplied {
+                            format!(" / max(img_{}__a, Epsilon)", name)
+                        } else {
+                            String::new()
+                        };
+                        stage_string.push_str(
+                            format!(
+                                "
+      vec3 img_{name}__rgb = IMG_THIS_PIXEL({image}).xyz{unpremultiply};",
+                                name = name,
+                                image = image,
+                                unpremultiply = unpremultiply
+                            )
+                            .as_str(),
+                        );
+                    }
+                }
+
+                if let Some(usages) = alpha_sources.get(source) {
+                    let kind_string = if source.kind == DataSourceKind::Color { "col" } else { "img" };
+                    if usages.require_hsv || usages.require_point {
+                        stage_string += &format!(
                             "
-            {{
-                \"NAME\" : \"float_{name}\",
-                \"LABEL\" : \"Float {name}\",
-                \"TYPE\" : \"float\"
-            }}",
-                        name = source.name
-                    );
-                },
-                DataSourceKind::Image => {
-                    // Create the shader string which loads the hsv vector for this image at a given pixel
-                    entry_string.push_str(
-                        format!(
+      vec3 {kind}_{name}__hsv = rgb2hsv({kind}_{name}__rgb);",
+                            kind = kind_string,
+                            name = mangle(&source.name)
+                        );
+                    }
+                    if usages.require_point {
+                        stage_string += &format!(
                             "
-      vec3 img_{name}_rgb = IMG_THIS_PIXEL({name}_img).xyz;",
-                            name = source.name
-                        )
-                        .as_str(),
-                    );
+      vec4 {kind}_{name} = hsv2half_spherical({kind}_{name}__hsv);",
+                            kind = kind_string,
+                            name = mangle(&source.name)
+                        );
+                    }
+                }
+            }
 
-                    shader_heading.push_str(
-                        format!(
-                            "
-            {{
-                \"NAME\" : \"{name}_img\",
-                \"LABEL\" : \"Image {name}\",
-                \"TYPE\" : \"image\"
+            // ====================================================================================
+            // == Build the shader strings and the output line                                   ==
+            // ====================================================================================
+            let path = format!("stages[{}]", stage_index);
+            let mut stage_shaders = stage.get_shader(&path, &mut function_names);
+            if stage_shaders.is_empty() {
+                return Err(ColorfoldError::Codegen(vec![ShaderError {
+                    line: 0,
+                    node: Some(path),
+                    message: String::from("Stage produced no shader code"),
+                }]));
+            }
+            let compute_call = stage_shaders.remove(0);
+            let output = if self.alpha.premultiplied {
+                format!(
+                    "float alpha_out = {};
+  gl_FragColor = vec4(color_out * alpha_out, alpha_out);",
+                    self.alpha.reference_string()
+                )
+            } else {
+                format!(
+                    "gl_FragColor = vec4(color_out, {});",
+                    self.alpha.reference_string()
+                )
+            };
+            stage_string += &format!(
+                "
+  // Convert the out_color back into rgb. Maintain alpha.
+  vec3 color_out = vec3(hsv2rgb(half_spherical2hsv({compute}.xyz)));
+  {output}",
+                compute = compute_call,
+                output = output
+            );
+            stage_shaders.reverse();
+            compute_shaders.append(&mut stage_shaders);
+
+            if multi_pass {
+                entry_string += &format!(
+                    "  {branch}if (PASSINDEX == {index})
+  {{{body}
+  }}
+",
+                    branch = if stage_index > 0 { "else " } else { "" },
+                    index = stage_index,
+                    body = stage_string.replace('\n', "\n  ")
+                );
+            } else {
+                entry_string += &stage_string[1..];
+                entry_string += "\n";
+            }
+        }
+        entry_string += "}\n";
+
+        shader_heading += "]";
+        if multi_pass {
+            let passes: Vec<String> = targets
+                .iter()
+                .map(|name| {
+                    format!(
+                        "
+        {{
+            \"TARGET\" : \"{name}__img\"
+        }}",
+                        name = mangle(name)
+                    )
+                })
+                .collect();
+            shader_heading += &format!(",\n    \"PASSES\": [{}\n    ]", passes.join(","));
+        }
+        shader_heading += "}*/";
+
+        // Bind all compute functions with the main function
+        let shading_str = compute_shaders.concat() + "\n" + &entry_string;
+        let shader = shader_heading + LIBRARY + &shading_str;
+        validate_isf(&shader).map_err(ColorfoldError::Codegen)?;
+        if save {
+            if let Some(path) = &self.location {
+                let path = path.with_extension("fs");
+                std::fs::write(&path, &shader).map_err(|e| ColorfoldError::io(&path, e))?;
+            }
+        }
+        Ok(shader)
+    }
+}
+
+/// The ISF INPUTS entry for a data source. The name is mangled into a valid identifier and the
+/// label shown in VDMX is the one declared for the input, or else the original name.
+fn input_declaration(source: &DataSource, input: Option<&Input>, header: &IsfHeader) -> String {
+    let (name, kind) = match source.kind {
+        DataSourceKind::Color => (format!("col_{}__rgba", mangle(&source.name)), "color"),
+        DataSourceKind::Float => (format!("float_{}", mangle(&source.name)), "float"),
+        DataSourceKind::Image | DataSourceKind::Stage => (header.image_input(source), "image"),
+    };
+    let label = input.map_or(source.name.as_str(), |x| x.label());
+    let mut fields = vec![
+        ("NAME", format!("\"{}\"", name)),
+        ("LABEL", json_string(label)),
+        ("TYPE", format!("\"{}\"", kind)),
+    ];
+    if let Some(input) = input {
+        fields.extend(input.isf_fields());
+    }
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!("\n                \"{}\" : {}", key, value))
+        .collect();
+    format!(
+        "
+            {{{}
             }}",
-                            name = source.name
-                        )
-                        .as_str(),
-                    );
+        fields.join(",")
+    )
+}
+
+impl FoldingMachine {
+    /// The declared default of every float input.
+    pub fn default_floats(&self) -> HashMap<String, f32> {
+        self.inputs
+            .iter()
+            .filter_map(|input| match input {
+                Input::Float {
+                    name,
+                    default: Some(x),
+                    ..
+                } => Some((name.clone(), *x)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The declared default of every color input.
+    pub fn default_colors(&self) -> HashMap<String, Color> {
+        self.inputs
+            .iter()
+            .filter_map(|input| match input {
+                Input::Color {
+                    name,
+                    default: Some(x),
+                    ..
+                } => Some((name.clone(), x.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every data source required by the machine which has no value in the given bindings.
+    pub fn unbound_sources(
+        &self,
+        images: &HashMap<String, Image>,
+        colors: &HashMap<String, Color>,
+        floats: &HashMap<String, f32>,
+    ) -> Result<Vec<DataSource>> {
+        Ok(self
+            .external_sources()?
+            .into_iter()
+            .filter(|source| match source.kind {
+                DataSourceKind::Image => !images.contains_key(&source.name),
+                DataSourceKind::Color => !colors.contains_key(&source.name),
+                DataSourceKind::Float => !floats.contains_key(&source.name),
+                DataSourceKind::Stage => false,
+            })
+            .collect())
+    }
+
+    /// Run every stage in order over a single pixel, making the output of each stage available to
+    /// the stages after it, and return the color and alpha written by the last stage.
+    fn evaluate_pixel(&self, names: &[String], pixel: &mut Pixel) -> Vec4 {
+        let mut output = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        for (stage, name) in self.stages.iter().zip(names) {
+            let color = folded_to_rgb(stage.evaluate(pixel));
+            let alpha = self.alpha.evaluate(pixel);
+            output = self.alpha.write(color, alpha).extend(alpha);
+            // Pass targets only hold displayable colors, so clamp before later stages read it.
+            let clamped = Vec3::new(
+                output.x.clamp(0.0, 1.0),
+                output.y.clamp(0.0, 1.0),
+                output.z.clamp(0.0, 1.0),
+            );
+            let sample = self.alpha.read(clamped, output.w.clamp(0.0, 1.0));
+            pixel.images.insert(name.clone(), sample);
+        }
+        output
+    }
+
+    /// Evaluate this folding machine on the cpu. Every image source the machine requires is
+    /// loaded from images and all of those images must share the same dimensions. Colors and
+    /// floats which are not bound evaluate to zero.
+    pub fn evaluate(
+        &self,
+        images: &mut HashMap<String, Image>,
+        colors: &HashMap<String, Color>,
+        floats: &HashMap<String, f32>,
+    ) -> Result<Image> {
+        self.validate().map_err(ColorfoldError::Invalid)?;
+        let names = self.stage_names();
+        let sources: Vec<DataSource> = self
+            .external_sources()?
+            .into_iter()
+            .filter(|source| source.kind == DataSourceKind::Image)
+            .collect();
+
+        let mut pixel = Pixel::new(colors, floats);
+
+        for source in &sources {
+            match images.get_mut(&source.name) {
+                Some(image) => image.load()?,
+                None => {
+                    return Err(ColorfoldError::semantic(format!(
+                        "No image bound to image source {}",
+                        source.name
+                    )))
                 }
             }
         }
+        let inputs: Vec<(&String, &Image)> = sources
+            .iter()
+            .map(|source| (&source.name, &images[&source.name]))
+            .collect();
 
-        shader_heading += "]}*/";
+        let (width, height) = match inputs.first() {
+            Some((_, image)) => image.dimensions(),
+            None => {
+                return Err(ColorfoldError::semantic(
+                    "Cannot evaluate a folding machine which does not use any images",
+                ))
+            }
+        };
+        if let Some((name, _)) = inputs
+            .iter()
+            .find(|(_, image)| image.dimensions() != (width, height))
+        {
+            return Err(ColorfoldError::semantic(format!(
+                "Image source {} does not match the {}x{} size of the other images",
+                name, width, height
+            )));
+        }
 
-        // ========================================================================================
-        // == Build the shader strings and the output line                                       ==
-        // ========================================================================================
+        let mut output = RgbaImage::new(width, height);
+        for (x, y, out) in output.enumerate_pixels_mut() {
+            for &(name, image) in &inputs {
+                let color = Sample::from(&image.get_pixel(x, y));
+                pixel
+                    .images
+                    .insert(name.clone(), self.alpha.read(color.rgb, color.alpha));
+            }
 
-        let mut compute_shaders = stage.get_shader();
-        let compute_call = compute_shaders.remove(0);
-        let end_string = format!(
-            "  // Convert the out_color back into rgb. Maintain alpha.
-  vec3 color_out = vec3(hsv2rgb(half_spherical2hsv({compute}.xyz)));
-  gl_FragColor = vec4(color_out, 1.0);
-}}
-",
-            compute = compute_call
-        );
+            *out = to_rgba8(self.evaluate_pixel(&names, &mut pixel));
+        }
+        let id = names.last().cloned().unwrap_or_default();
+        Ok(Image::from_pixels(id, output))
+    }
+}
 
-        // Bind all compute functions with the main function
-        let mut shading_str = entry_string + &end_string;
-        for compute_shader in compute_shaders {
-            shading_str = compute_shader + &shading_str;
+impl FoldingMachine {
+    /// The name of the only image source the machine reads from. Machines which read from
+    /// several images cannot be expressed as a color lookup table.
+    pub fn lut_source(&self) -> Result<String> {
+        let images: Vec<String> = self
+            .external_sources()?
+            .into_iter()
+            .filter(|source| source.kind == DataSourceKind::Image)
+            .map(|source| source.name)
+            .collect();
+        match images.len() {
+            1 => Ok(images[0].clone()),
+            0 => Err(ColorfoldError::semantic(
+                "A lookup table needs a folding machine which filters an image",
+            )),
+            _ => Err(ColorfoldError::semantic(format!(
+                "A lookup table can only be baked from a folding machine with one image source, found {}",
+                images.join(", ")
+            ))),
         }
-        let shader = shader_heading + &include_str!("../shaders/lib.fs") + &shading_str;
-        if save {
-            if let &Some(ref path) = &self.location {
-                std::fs::write(path.with_extension("fs"), &shader)?;
+    }
+
+    /// Sample the machine over the whole rgb cube at size x size x size grid points. The machine
+    /// must read from exactly one image source, which is fed each grid point in turn.
+    pub fn bake_lut(
+        &self,
+        size: usize,
+        colors: &HashMap<String, Color>,
+        floats: &HashMap<String, f32>,
+    ) -> Result<Lut> {
+        if size < 2 {
+            return Err(ColorfoldError::semantic(
+                "A lookup table needs a grid size of at least 2",
+            ));
+        }
+        self.validate().map_err(ColorfoldError::Invalid)?;
+        let source = self.lut_source()?;
+        let names = self.stage_names();
+
+        let mut pixel = Pixel::new(colors, floats);
+        let scale = (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let input = Vec3::new(r as f32 / scale, g as f32 / scale, b as f32 / scale);
+                    pixel.images.insert(source.clone(), Sample::from_rgb(input));
+                    table.push(self.evaluate_pixel(&names, &mut pixel).xyz());
+                }
             }
         }
+        let title = names.last().cloned().unwrap_or_default();
+        Ok(Lut::new(title, size, table))
+    }
+}
+
+impl FoldingMachine {
+    /// Alternate output mode which bakes the machine into a HaldCLUT saved at hald_path and
+    /// returns an ISF shader which filters the image by sampling it. The shader costs the same no
+    /// matter how many operations or stages the machine has, but float and color sources are fixed
+    /// at bake time. The HaldCLUT is imported by file name so the shader must be saved next to it.
+    pub fn lut_shader(
+        &self,
+        size: usize,
+        colors: &HashMap<String, Color>,
+        floats: &HashMap<String, f32>,
+        hald_path: &Path,
+    ) -> Result<String> {
+        let source = DataSource {
+            name: self.lut_source()?,
+            kind: DataSourceKind::Image,
+        };
+        let alpha = match self.alpha.source {
+            AlphaSource::Opaque => String::from("1.0"),
+            AlphaSource::Image(ref name) if *name == source.name => String::from("source_rgba.a"),
+            _ => {
+                return Err(ColorfoldError::semantic(
+                    "A lookup table shader can only take its alpha from the image it filters",
+                ))
+            }
+        };
+        let lut = self.bake_lut(size, colors, floats)?;
+        lut.save_hald(hald_path)?;
+
+        let input = self.inputs.iter().find(|x| x.source() == source);
+        let heading = self.header.opening() + &input_declaration(&source, input, &self.header);
+        let (load, output) = if self.alpha.premultiplied {
+            (
+                "vec3 color_in = source_rgba.rgb / max(source_rgba.a, 0.0000001);",
+                format!(
+                    "float alpha_out = {};
+  gl_FragColor = vec4(color_out * alpha_out, alpha_out);",
+                    alpha
+                ),
+            )
+        } else {
+            (
+                "vec3 color_in = source_rgba.rgb;",
+                format!("gl_FragColor = vec4(color_out, {});", alpha),
+            )
+        };
+        let load = format!(
+            "vec4 source_rgba = IMG_THIS_PIXEL({});\n  {}",
+            self.header.image_input(&source),
+            load
+        );
+
+        let file_name = hald_path
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let shader = lut.isf_shader(&heading, &load, &output, &file_name)?;
+        validate_isf(&shader).map_err(ColorfoldError::Codegen)?;
         Ok(shader)
     }
 }
 
+fn to_rgba8(color: Vec4) -> Rgba<u8> {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgba([channel(color.x), channel(color.y), channel(color.z), channel(color.w)])
+}
+
 // // ================================================================================================
 // // == Serde Serialization for parsing input files.                                               ==
 // // ================================================================================================
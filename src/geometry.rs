@@ -16,8 +16,17 @@
 //!   - Spheres
 //!
 
+#![allow(
+    clippy::borrowed_box,
+    clippy::match_ref_pats,
+    clippy::needless_borrowed_reference,
+    clippy::useless_format,
+)]
+
 use data::{ColorData, FloatData};
 use dependency::DataDependencyGraph;
+use evaluation::{lin_interp, lin_interp_bounded, rot_interp, rot_interp_bounded, Pixel, Vec4};
+use validation::Validation;
 
 /// Geom0D represent objects which are points. Geom0D may be specific points on higher dimensional
 /// objects such as lines, circles, planes, etc. which are obtained deterministically by some
@@ -104,6 +113,15 @@ impl Geom0D {
         }
     }
 
+    /// Cpu evaluation of Geom0D objects. Mirrors the shader generated by get_shader.
+    pub fn evaluate(&self, pixel: &Pixel) -> Vec4 {
+        use geometry::Geom0D::*;
+        match self {
+            Point(c) => c.evaluate_hsv_spherical(pixel),
+            Evaluation1D(geom, evaluation_technique) => evaluation_technique.evaluate(geom, pixel),
+        }
+    }
+
     pub fn get_required_sources(&self, graph: &mut DataDependencyGraph) {
         use geometry::Geom0D::*;
 
@@ -118,6 +136,18 @@ impl Geom0D {
             &Point(ref data) => data.get_required_sources(graph),
         }
     }
+
+    /// Report every problem with this point and the objects it is built from.
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        use geometry::Geom0D::*;
+        match self {
+            Point(data) => data.validate(path, validation),
+            Evaluation1D(geom, evaluation_technique) => {
+                geom.validate(path, validation);
+                evaluation_technique.validate(path, validation);
+            }
+        }
+    }
 }
 
 impl GeomEvalTechnique1D {
@@ -129,6 +159,23 @@ impl GeomEvalTechnique1D {
             &Value(ref data) => (*geom).get_shader(data.reference_string()),
         }
     }
+
+    /// Cpu evaluation of the point on geom picked out by this technique.
+    pub fn evaluate(&self, geom: &Geom1D, pixel: &Pixel) -> Vec4 {
+        use geometry::GeomEvalTechnique1D::*;
+
+        match self {
+            Value(data) => geom.evaluate(data.evaluate(pixel), pixel),
+        }
+    }
+
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        use geometry::GeomEvalTechnique1D::*;
+
+        match self {
+            Value(data) => data.validate(&format!("{}.value", path), validation),
+        }
+    }
 }
 
 impl Geom1D {
@@ -142,7 +189,7 @@ impl Geom1D {
                 ref start_time,
                 ref end_time,
             } => format!(
-                "lin_interp({}, {}, ({} - {})/({} - {}))",
+                "lin_interp({}, {}, ({} - {:?})/({:?} - {:?}))",
                 start.get_shader(),
                 end.get_shader(),
                 param,
@@ -156,7 +203,7 @@ impl Geom1D {
                 ref start_time,
                 ref end_time,
             } => format!(
-                "lin_interp_bounded({}, {}, ({} - {})/({} - {}))",
+                "lin_interp_bounded({}, {}, ({} - {:?})/({:?} - {:?}))",
                 start.get_shader(),
                 end.get_shader(),
                 param,
@@ -170,7 +217,7 @@ impl Geom1D {
                 ref start_time,
                 ref end_time,
             } => format!(
-                "rot_interp({}, {}, ({} - {})/({} - {}))",
+                "rot_interp({}, {}, ({} - {:?})/({:?} - {:?}))",
                 start.get_shader(),
                 end.get_shader(),
                 param,
@@ -184,7 +231,7 @@ impl Geom1D {
                 ref start_time,
                 ref end_time,
             } => format!(
-                "rot_interp_bounded({}, {}, ({} - {})/({} - {}))",
+                "rot_interp_bounded({}, {}, ({} - {:?})/({:?} - {:?}))",
                 start.get_shader(),
                 end.get_shader(),
                 param,
@@ -195,6 +242,53 @@ impl Geom1D {
         }
     }
 
+    /// Cpu evaluation of the point on a 1D geometry at the given parameter.
+    pub fn evaluate(&self, param: f32, pixel: &Pixel) -> Vec4 {
+        use geometry::Geom1D::*;
+        match self {
+            Line {
+                start,
+                end,
+                start_time,
+                end_time,
+            } => lin_interp(
+                start.evaluate(pixel),
+                end.evaluate(pixel),
+                (param - start_time) / (end_time - start_time),
+            ),
+            LineSegment {
+                start,
+                end,
+                start_time,
+                end_time,
+            } => lin_interp_bounded(
+                start.evaluate(pixel),
+                end.evaluate(pixel),
+                (param - start_time) / (end_time - start_time),
+            ),
+            Arc {
+                start,
+                end,
+                start_time,
+                end_time,
+            } => rot_interp(
+                start.evaluate(pixel),
+                end.evaluate(pixel),
+                (param - start_time) / (end_time - start_time),
+            ),
+            ArcSegment {
+                start,
+                end,
+                start_time,
+                end_time,
+            } => rot_interp_bounded(
+                start.evaluate(pixel),
+                end.evaluate(pixel),
+                (param - start_time) / (end_time - start_time),
+            ),
+        }
+    }
+
     pub fn get_required_sources(&self, graph: &mut DataDependencyGraph) {
         use geometry::Geom1D::*;
 
@@ -225,4 +319,46 @@ impl Geom1D {
             }
         }
     }
+
+    /// Report every problem with this geometry. Objects whose start and end times are equal
+    /// would divide by zero when evaluated.
+    pub fn validate(&self, path: &str, validation: &mut Validation) {
+        use geometry::Geom1D::*;
+
+        match self {
+            Line {
+                start,
+                end,
+                start_time,
+                end_time,
+            }
+            | LineSegment {
+                start,
+                end,
+                start_time,
+                end_time,
+            }
+            | Arc {
+                start,
+                end,
+                start_time,
+                end_time,
+            }
+            | ArcSegment {
+                start,
+                end,
+                start_time,
+                end_time,
+            } => {
+                if start_time == end_time {
+                    validation.error(
+                        path,
+                        format!("start_time and end_time are both {:?}", start_time),
+                    );
+                }
+                start.validate(&format!("{}.start", path), validation);
+                end.validate(&format!("{}.end", path), validation);
+            }
+        }
+    }
 }
@@ -0,0 +1,534 @@
+//! # GLSL Validation
+//! Offline checking of generated ISF shaders. The shader is wrapped in a prelude declaring
+//! everything ISF would provide (inputs, imported images and the IMG_* macros) and then parsed
+//! and type checked with naga's glsl front-end, so invalid code is caught before VDMX sees it.
+//!
+//! Generated compute code is tagged with comments naming the descriptor node it came from, such
+//! as `// stages[0].operations[1]`, which are used to map errors back to the descriptor.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// An error found in a generated shader.
+pub struct ShaderError {
+    /// Line in the shader the error occurred on, starting at 1. Zero if the error could not be
+    /// placed in the shader itself.
+    pub line: usize,
+    /// Path of the descriptor node which produced the offending code, if known.
+    pub node: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.node {
+            Some(ref node) => write!(f, "line {} ({}): {}", self.line, node, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+/// Map any name to an identifier which is valid in GLSL. Names which are already such
+/// identifiers are kept as they are. Any other name gets a `glx` prefix, which no kept name can
+/// start with, followed by its ascii letters and digits with every other character written as
+/// `_` and the hex of its utf-8 bytes. Distinct names always map to distinct identifiers, and no
+/// identifier returned here contains `__`, so the identifiers generated from a name join it to
+/// their suffix with `__`, such as `img_{name}__rgb` or `{name}__img`, and can never be equal to
+/// an identifier generated from another name.
+pub fn mangle(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.contains("__")
+        && !name.ends_with('_')
+        && !name.starts_with("gl");
+    if plain {
+        return name.to_owned();
+    }
+
+    let mut mangled = String::from("glx");
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            mangled.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                mangled += &format!("_{:02x}", byte);
+            }
+        }
+    }
+    mangled
+}
+
+/// A name written as a json string literal, for placing user supplied names such as LABELs in
+/// the ISF header.
+pub fn json_string(name: &str) -> String {
+    serde_json::to_string(name).unwrap_or_else(|_| String::from("\"\""))
+}
+
+/// The shader library included verbatim in every generated shader.
+pub const LIBRARY: &str = include_str!("../shaders/lib.fs");
+
+/// Names declared at the top level of the shader library: its functions, constants and macros.
+pub fn library_symbols() -> Vec<String> {
+    LIBRARY
+        .lines()
+        .filter(|line| !line.starts_with(char::is_whitespace) && !line.starts_with("//"))
+        .filter_map(|line| {
+            let words: Vec<&str> = line
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .filter(|x| !x.is_empty())
+                .collect();
+            if line.starts_with("#define") {
+                words.get(1).cloned()
+            } else if line.starts_with("const ") {
+                words.get(2).cloned()
+            } else {
+                match line.find('(') {
+                    Some(x) if line[..x].split_whitespace().count() == 2 => words.get(1).cloned(),
+                    _ => None,
+                }
+            }
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// FunctionNames hands out a distinct GLSL function name for every compute object and curve in
+/// a shader, so computes which share a name in different parts of a descriptor do not
+/// collide. Function names are prefixed so they can never clash with the shader library or GLSL
+/// builtins.
+#[derive(Default)]
+pub struct FunctionNames {
+    computes: HashMap<String, usize>,
+    curves: usize,
+}
+
+impl FunctionNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A name for the function of a curve, which is never the name of a compute.
+    pub fn allocate_curve(&mut self) -> String {
+        self.curves += 1;
+        format!("curve{}", self.curves)
+    }
+
+    pub fn allocate(&mut self, name: &str) -> String {
+        let mangled = mangle(name);
+        let count = self.computes.entry(mangled.clone()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            format!("compute_{}", mangled)
+        } else {
+            format!("compute{}_{}", count, mangled)
+        }
+    }
+}
+
+/// Parse and type check an ISF fragment shader, returning every error found.
+pub fn validate_isf(shader: &str) -> Result<(), Vec<ShaderError>> {
+    let header = match isf_header(shader) {
+        Ok(x) => x,
+        Err(message) => {
+            return Err(vec![ShaderError {
+                line: 1,
+                node: None,
+                message,
+            }])
+        }
+    };
+
+    let prelude = prelude(&header);
+    let source = format!("{}{}", prelude, shader);
+    let prelude_lines = prelude.matches('\n').count();
+    let locate = |span: naga::Span| -> (usize, Option<String>) {
+        match span.to_range() {
+            Some(range) => {
+                let line = source[..range.start].matches('\n').count() + 1;
+                if line <= prelude_lines {
+                    (0, None)
+                } else {
+                    let line = line - prelude_lines;
+                    (line, node_at(shader, line))
+                }
+            }
+            None => (0, None),
+        }
+    };
+
+    let mut frontend = naga::front::glsl::Frontend::default();
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let module = match frontend.parse(&options, &source) {
+        Ok(x) => x,
+        Err(e) => {
+            return Err(e
+                .errors
+                .into_iter()
+                .map(|error| {
+                    let (line, node) = locate(error.meta);
+                    ShaderError {
+                        line,
+                        node,
+                        message: error.kind.to_string(),
+                    }
+                })
+                .collect())
+        }
+    };
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    );
+    if let Err(error) = validator.validate(&module) {
+        let (line, node) = error
+            .spans()
+            .next()
+            .map(|&(span, _)| locate(span))
+            .unwrap_or((0, None));
+        let mut message = error.as_inner().to_string();
+        let mut source = std::error::Error::source(error.as_inner());
+        while let Some(inner) = source {
+            message = format!("{}: {}", message, inner);
+            source = inner.source();
+        }
+        return Err(vec![ShaderError {
+            line,
+            node,
+            message,
+        }]);
+    }
+
+    let errors: Vec<ShaderError> = module
+        .functions
+        .iter()
+        .map(|(_, function)| function)
+        .chain(module.entry_points.iter().map(|entry| &entry.function))
+        .flat_map(|function| strict_errors(function, &source))
+        .map(|(span, message)| {
+            let (line, node) = locate(span);
+            ShaderError {
+                line,
+                node,
+                message,
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Problems naga's glsl front-end lets through but stricter compilers, such as GLSL ES ones,
+/// reject: integers converted implicitly to floats and locals redeclaring a parameter in the
+/// outermost scope of a function. Explicit conversions through a constructor such as
+/// `float(n)` or `vec4(0, 0, 0, 1)` are allowed.
+fn strict_errors(function: &naga::Function, source: &str) -> Vec<(naga::Span, String)> {
+    use naga::{Expression, Literal, ScalarKind};
+
+    let text = |span: naga::Span| span.to_range().map_or("", |range| &source[range]);
+    let expression_text = |handle| text(function.expressions.get_span(handle));
+
+    let mut constructed = HashSet::new();
+    for (handle, expression) in function.expressions.iter() {
+        match *expression {
+            Expression::Compose { ref components, .. }
+                if is_constructor(expression_text(handle)) =>
+            {
+                constructed.extend(components.iter().cloned())
+            }
+            Expression::Splat { value, .. } if is_constructor(expression_text(handle)) => {
+                constructed.insert(value);
+            }
+            _ => {}
+        }
+    }
+
+    let mut errors = Vec::new();
+    for (handle, expression) in function.expressions.iter() {
+        let implicit = match *expression {
+            // The front-end folds converted integer literals into float literals, which can
+            // only be told apart by their source text.
+            Expression::Literal(Literal::F32(_)) | Expression::Literal(Literal::F64(_)) => {
+                is_integer_text(expression_text(handle))
+            }
+            Expression::As {
+                kind: ScalarKind::Float,
+                convert: Some(_),
+                ..
+            } => !is_constructor(expression_text(handle)),
+            _ => false,
+        };
+        if implicit && !constructed.contains(&handle) {
+            errors.push((
+                function.expressions.get_span(handle),
+                format!(
+                    "Integer `{}` is used as a float without an explicit conversion",
+                    expression_text(handle)
+                ),
+            ));
+        }
+    }
+
+    // Every parameter is copied into a local of the same name ahead of the locals declared in
+    // the body, and the body starts at the first brace after the last parameter.
+    let parameters = function.arguments.len();
+    let locals: Vec<_> = function.local_variables.iter().collect();
+    let body = locals
+        .get(parameters.wrapping_sub(1))
+        .and_then(|&(handle, _)| function.local_variables.get_span(handle).to_range())
+        .and_then(|range| source[range.end..].find('{').map(|x| range.end + x));
+    if let Some(body) = body {
+        let names: HashSet<&str> = locals[..parameters]
+            .iter()
+            .filter_map(|&(_, local)| local.name.as_deref())
+            .collect();
+        for &(handle, local) in &locals[parameters..] {
+            let span = function.local_variables.get_span(handle);
+            let name = match local.name {
+                Some(ref name) if names.contains(name.as_str()) => name,
+                _ => continue,
+            };
+            let start = match span.to_range() {
+                Some(range) => range.start,
+                None => continue,
+            };
+            let depth = source[body..start].matches('{').count() as isize
+                - source[body..start].matches('}').count() as isize;
+            if depth == 1 {
+                errors.push((span, format!("`{}` redeclares a parameter", name)));
+            }
+        }
+    }
+    errors
+}
+
+/// Whether code is a call to a type constructor such as `float(x)` or `vec3(1)`.
+fn is_constructor(code: &str) -> bool {
+    let name: String = code
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+    let base = name.trim_end_matches(|c: char| "234".contains(c));
+    let constructor = ["float", "int", "uint", "bool"].contains(&name.as_str())
+        || (["vec", "ivec", "uvec", "bvec"].contains(&base) && name.len() == base.len() + 1)
+        || name.starts_with("mat");
+    constructor && code[name.len()..].trim_start().starts_with('(')
+}
+
+/// Whether code is an integer literal, or arithmetic on integer literals only.
+fn is_integer_text(code: &str) -> bool {
+    code.chars().any(|c| c.is_ascii_digit())
+        && code
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_whitespace() || "+-*/%()".contains(c))
+}
+
+/// The json header at the top of an ISF shader.
+fn isf_header(shader: &str) -> Result<serde_json::Value, String> {
+    let start = match shader.find("/*") {
+        Some(x) => x + 2,
+        None => return Err(String::from("Missing the ISF json header")),
+    };
+    let end = match shader[start..].find("*/") {
+        Some(x) => start + x,
+        None => return Err(String::from("Unterminated ISF json header")),
+    };
+    serde_json::from_str(&shader[start..end])
+        .map_err(|e| format!("Malformed ISF json header: {}", e))
+}
+
+/// Declarations standing in for what ISF provides to a shader.
+fn prelude(header: &serde_json::Value) -> String {
+    let mut images = Vec::new();
+    let mut uniforms = Vec::new();
+    if let Some(inputs) = header["INPUTS"].as_array() {
+        for input in inputs {
+            let name = match input["NAME"].as_str() {
+                Some(x) => x,
+                None => continue,
+            };
+            match input["TYPE"].as_str() {
+                Some("image") => images.push(name.to_owned()),
+                Some("float") => uniforms.push(format!("float {}", name)),
+                Some("color") => uniforms.push(format!("vec4 {}", name)),
+                Some("bool") => uniforms.push(format!("bool {}", name)),
+                Some("long") => uniforms.push(format!("int {}", name)),
+                Some("point2D") => uniforms.push(format!("vec2 {}", name)),
+                _ => {}
+            }
+        }
+    }
+    if let Some(imported) = header["IMPORTED"].as_object() {
+        images.extend(imported.keys().cloned());
+    }
+    if let Some(passes) = header["PASSES"].as_array() {
+        for pass in passes {
+            if let Some(target) = pass["TARGET"].as_str() {
+                images.push(target.to_owned());
+            }
+        }
+    }
+    uniforms.push(String::from("int PASSINDEX"));
+    uniforms.push(String::from("vec2 RENDERSIZE"));
+    uniforms.push(String::from("float TIME"));
+
+    let mut prelude = String::from(
+        "#version 450 core
+layout(location = 0) in vec2 isf_FragNormCoord;
+layout(location = 0) out vec4 isf_FragColor;
+layout(set = 0, binding = 0) uniform sampler isf_sampler;
+#define gl_FragColor isf_FragColor
+#define gl_FragCoord (vec4(isf_FragNormCoord * RENDERSIZE, 0.0, 1.0))
+#define IMG_NORM_PIXEL(image, coord) texture(sampler2D(image, isf_sampler), coord)
+#define IMG_THIS_NORM_PIXEL(image) IMG_NORM_PIXEL(image, isf_FragNormCoord)
+#define IMG_THIS_PIXEL(image) IMG_THIS_NORM_PIXEL(image)
+#define IMG_SIZE(image) vec2(textureSize(sampler2D(image, isf_sampler), 0))
+#define IMG_PIXEL(image, coord) IMG_NORM_PIXEL(image, (coord) / IMG_SIZE(image))
+",
+    );
+    prelude += &format!(
+        "layout(set = 0, binding = 1) uniform IsfInputs {{ {}; }};\n",
+        uniforms.join("; ")
+    );
+    for (i, image) in images.iter().enumerate() {
+        prelude += &format!(
+            "layout(set = 1, binding = {}) uniform texture2D {};\n",
+            i, image
+        );
+    }
+    prelude
+}
+
+/// The descriptor node whose generated code contains the given line, found from the closest
+/// node comment at or before it.
+fn node_at(shader: &str, line: usize) -> Option<String> {
+    shader
+        .lines()
+        .take(line)
+        .filter_map(|x| x.trim().strip_prefix("// stages["))
+        .last()
+        .map(|x| format!("stages[{}", x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader(body: &str) -> String {
+        format!(
+            "/*{{ \"INPUTS\": [] }}*/
+float f(float x, vec3 v, int n)
+{{
+    {}
+    return x + v.x;
+}}
+
+void main()
+{{
+    gl_FragColor = vec4(f(1.0, vec3(1.0), 2));
+}}
+",
+            body
+        )
+    }
+
+    fn assert_rejected(body: &str) {
+        assert!(
+            validate_isf(&shader(body)).is_err(),
+            "`{}` was accepted",
+            body
+        );
+    }
+
+    #[test]
+    fn mangle_keeps_names_apart_from_suffixes() {
+        assert_eq!(mangle("x_rgb"), "x_rgb");
+        assert_eq!(mangle("My Photo"), "glxMy_20Photo");
+        assert_eq!(mangle("a__b"), "glxa_5f_5fb");
+        assert_eq!(mangle("glow"), "glxglow");
+
+        let names = [
+            "x",
+            "x_rgb",
+            "x_",
+            "x__rgb",
+            "x rgb",
+            "x_20rgb",
+            "glxx_20rgb",
+            "gl",
+            "1x",
+            "é",
+        ];
+        let mangled: Vec<String> = names.iter().map(|x| mangle(x)).collect();
+        for (name, identifier) in names.iter().zip(&mangled) {
+            assert!(
+                !identifier.contains("__") && !identifier.ends_with('_'),
+                "{} mangles to {}",
+                name,
+                identifier
+            );
+        }
+        let generated: HashSet<String> = mangled
+            .iter()
+            .flat_map(|m| {
+                vec![
+                    format!("img_{}", m),
+                    format!("img_{}__rgb", m),
+                    format!("img_{}__hsv", m),
+                    format!("img_{}__a", m),
+                    format!("{}__img", m),
+                ]
+            })
+            .collect();
+        assert_eq!(generated.len(), names.len() * 5);
+    }
+
+    #[test]
+    fn library_symbols_are_top_level_names() {
+        let symbols = library_symbols();
+        for symbol in &["rgb2hsv", "M_PI", "Epsilon", "point_twist", "BezierStrict"] {
+            assert!(symbols.iter().any(|x| x == symbol), "{} is missing", symbol);
+        }
+        for local in &["K", "total_inv_weight", "in_vec", "Color"] {
+            assert!(!symbols.iter().any(|x| x == local), "{} is local", local);
+        }
+    }
+
+    #[test]
+    fn accepts_explicit_conversions() {
+        let body = "vec4 a = vec4(0, 0, 0, -1);
+    float b = float(n) / 2.0;
+    vec3 c = vec3(1) * float(n);
+    {
+        float x = 2.0;
+    }";
+        if let Err(errors) = validate_isf(&shader(body)) {
+            let messages: Vec<String> = errors.iter().map(|x| x.to_string()).collect();
+            panic!("{}", messages.join("\n"));
+        }
+    }
+
+    #[test]
+    fn rejects_integer_literals_as_floats() {
+        assert_rejected("float total_inv_weight = 0;");
+        assert_rejected("float a = 1 / x;");
+        assert_rejected("float a = x * (2 + 1);");
+        assert_rejected("float a = max(x, 0);");
+    }
+
+    #[test]
+    fn rejects_integer_operands_as_floats() {
+        assert_rejected("vec3 a = v / n;");
+        assert_rejected("float a = x + n;");
+    }
+
+    #[test]
+    fn rejects_redeclared_parameters() {
+        assert_rejected("vec3 v = vec3(1.0);");
+        assert_rejected("float b = 1.0;\n    float x = b;");
+    }
+}
@@ -1,9 +1,18 @@
+use color::Color;
+use error::{self, ColorfoldError};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::Error;
+use std::path::Path;
 
+/// Image is a named image file. The pixels are only decoded the first time they are needed,
+/// which is tracked by is_loaded.
+#[derive(Clone)]
 pub struct Image {
     pub location: String,
     pub id: String,
     pub is_loaded: bool,
+    pixels: Option<RgbaImage>,
 }
 
 impl Image {
@@ -12,13 +21,83 @@ impl Image {
             location,
             id,
             is_loaded: false,
+            pixels: None,
         }
     }
-}
 
-impl Clone for Image {
-    fn clone(&self) -> Image {
-        Image::new(self.id.clone(), self.location.clone())
+    /// Create an image which already holds its pixels and is not backed by a file.
+    pub fn from_pixels(id: String, pixels: RgbaImage) -> Image {
+        Image {
+            location: String::new(),
+            id,
+            is_loaded: true,
+            pixels: Some(pixels),
+        }
+    }
+
+    /// Decode the image at location if it has not been loaded yet.
+    pub fn load(&mut self) -> error::Result<()> {
+        if !self.is_loaded {
+            let pixels = image::open(&self.location).map_err(|e| {
+                ColorfoldError::io(
+                    Path::new(&self.location),
+                    Error::other(format!("Could not load image {}: {}", self.id, e)),
+                )
+            })?;
+            self.pixels = Some(pixels.to_rgba8());
+            self.is_loaded = true;
+        }
+        Ok(())
+    }
+
+    /// Encode the pixels of this image to a file. The format is chosen by the file extension and
+    /// alpha is dropped for formats which cannot store it.
+    pub fn save(&self, path: &Path) -> error::Result<()> {
+        match self.pixels {
+            Some(ref pixels) => {
+                let result = match ImageFormat::from_path(path) {
+                    Ok(ImageFormat::Jpeg) => {
+                        DynamicImage::ImageRgba8(pixels.clone()).to_rgb8().save(path)
+                    }
+                    _ => pixels.save(path),
+                };
+                result.map_err(|e| ColorfoldError::io(path, Error::other(e)))
+            }
+            None => Err(ColorfoldError::semantic(format!(
+                "Cannot save image {} before it is loaded",
+                self.id
+            ))),
+        }
+    }
+
+    /// Width of the image in pixels. Zero until the image is loaded.
+    pub fn width(&self) -> u32 {
+        self.pixels.as_ref().map_or(0, |x| x.width())
+    }
+
+    /// Height of the image in pixels. Zero until the image is loaded.
+    pub fn height(&self) -> u32 {
+        self.pixels.as_ref().map_or(0, |x| x.height())
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width(), self.height())
+    }
+
+    /// The decoded pixel buffer, if the image has been loaded.
+    pub fn pixels(&self) -> Option<&RgbaImage> {
+        self.pixels.as_ref()
+    }
+
+    /// The color at a given pixel. Panics if the image is not loaded or the pixel is out of
+    /// bounds.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Color {
+        let pixels = self
+            .pixels
+            .as_ref()
+            .unwrap_or_else(|| panic!("Image {} read before being loaded", self.id));
+        let &Rgba([r, g, b, a]) = pixels.get_pixel(x, y);
+        Color(r, g, b, a)
     }
 }
 
@@ -0,0 +1,252 @@
+//! # Lookup Tables
+//! A folding machine which only filters a single image is a pure color mapping, so it can be
+//! baked into a 3D lookup table and used by tools which do not understand ISF shaders.
+
+use error::{ColorfoldError, Result};
+use evaluation::Vec3;
+use glsl::json_string;
+use image::{Rgba, RgbaImage};
+use imaging::Image;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Lut holds the output color of a color mapping at every point of a size x size x size grid
+/// over the rgb cube. Entries are stored with red changing fastest, then green, then blue.
+pub struct Lut {
+    pub title: String,
+    pub size: usize,
+    pub table: Vec<Vec3>,
+}
+
+impl Lut {
+    pub fn new(title: String, size: usize, table: Vec<Vec3>) -> Self {
+        assert_eq!(table.len(), size * size * size);
+        Lut { title, size, table }
+    }
+
+    /// The output color at a grid point.
+    pub fn get(&self, r: usize, g: usize, b: usize) -> Vec3 {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    /// The table in the Adobe/IRIDAS .cube format.
+    pub fn cube_string(&self) -> String {
+        let mut cube = String::new();
+        let _ = writeln!(cube, "# Generated by colorfold");
+        let _ = writeln!(cube, "TITLE \"{}\"", self.title.replace('"', "'"));
+        let _ = writeln!(cube, "LUT_3D_SIZE {}", self.size);
+        let _ = writeln!(cube, "DOMAIN_MIN 0.0 0.0 0.0");
+        let _ = writeln!(cube, "DOMAIN_MAX 1.0 1.0 1.0");
+        for color in &self.table {
+            let _ = writeln!(
+                cube,
+                "{:.6} {:.6} {:.6}",
+                color.x.clamp(0.0, 1.0),
+                color.y.clamp(0.0, 1.0),
+                color.z.clamp(0.0, 1.0)
+            );
+        }
+        cube
+    }
+
+    pub fn save_cube(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.cube_string()).map_err(|e| ColorfoldError::io(path, e))
+    }
+
+    /// The level of the HaldCLUT holding this table. A HaldCLUT of level L has L * L grid points
+    /// along each axis, so only tables with a square size have one.
+    pub fn hald_level(&self) -> Result<usize> {
+        let level = (self.size as f64).sqrt().round() as usize;
+        if level * level == self.size {
+            Ok(level)
+        } else {
+            Err(ColorfoldError::semantic(format!(
+                "A HaldCLUT needs a square grid size such as 36, 64 or 144, not {}",
+                self.size
+            )))
+        }
+    }
+
+    /// The table as a HaldCLUT image. The image is level^3 pixels square with the table entries
+    /// laid out row by row from the top left.
+    pub fn hald(&self) -> Result<Image> {
+        let level = self.hald_level()?;
+        let width = (level * level * level) as u32;
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let pixels = RgbaImage::from_fn(width, width, |x, y| {
+            let color = self.table[(y * width + x) as usize];
+            Rgba([channel(color.x), channel(color.y), channel(color.z), 255])
+        });
+        Ok(Image::from_pixels(self.title.clone(), pixels))
+    }
+
+    pub fn save_hald(&self, path: &Path) -> Result<()> {
+        self.hald()?.save(path)
+    }
+
+    /// An ISF shader which filters an image by sampling this table from a HaldCLUT imported from
+    /// hald_path. Lookups are trilinearly interpolated between grid points. The heading is the
+    /// json header up to the end of its INPUTS, and main looks up the color given by load and then
+    /// runs output to write color_out into gl_FragColor.
+    pub fn isf_shader(
+        &self,
+        heading: &str,
+        load: &str,
+        output: &str,
+        hald_path: &str,
+    ) -> Result<String> {
+        let level = self.hald_level()?;
+        Ok(format!(
+            "{heading}],
+    \"IMPORTED\": {{
+        \"lut\": {{
+            \"PATH\": {path}
+        }}
+    }}
+}}*/
+
+const float LUT_SIZE = {size}.0;
+const float LUT_WIDTH = {width}.0;
+
+/**
+ * Read the table entry at a grid point. Entries are laid out from the top left of the HaldCLUT
+ * while pixel coordinates start from the bottom left.
+ */
+vec3 lut_entry(vec3 index)
+{{
+  float i = index.x + LUT_SIZE * (index.y + LUT_SIZE * index.z);
+  vec2 pixel = vec2(mod(i, LUT_WIDTH), LUT_WIDTH - 1.0 - floor(i / LUT_WIDTH)) + 0.5;
+  return IMG_PIXEL(lut, pixel).rgb;
+}}
+
+vec3 sample_lut(vec3 color)
+{{
+  vec3 position = clamp(color, 0.0, 1.0) * (LUT_SIZE - 1.0);
+  vec3 low = floor(position);
+  vec3 high = min(low + 1.0, LUT_SIZE - 1.0);
+  vec3 t = position - low;
+
+  vec3 c00 = mix(lut_entry(vec3(low.x, low.y, low.z)), lut_entry(vec3(high.x, low.y, low.z)), t.x);
+  vec3 c10 = mix(lut_entry(vec3(low.x, high.y, low.z)), lut_entry(vec3(high.x, high.y, low.z)), t.x);
+  vec3 c01 = mix(lut_entry(vec3(low.x, low.y, high.z)), lut_entry(vec3(high.x, low.y, high.z)), t.x);
+  vec3 c11 = mix(lut_entry(vec3(low.x, high.y, high.z)), lut_entry(vec3(high.x, high.y, high.z)), t.x);
+  return mix(mix(c00, c10, t.y), mix(c01, c11, t.y), t.z);
+}}
+
+void main()
+{{
+  {load}
+  vec3 color_out = sample_lut(color_in);
+  {output}
+}}
+",
+            heading = heading,
+            load = load,
+            output = output,
+            path = json_string(hald_path),
+            size = self.size,
+            width = level * level * level,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use folding::FoldingMachine;
+    use std::collections::HashMap;
+
+    const IDENTITY: &str = r#"(
+    stages: [
+        Compute(
+            name: "Identity",
+            operations: [
+                Translation(
+                    start_point: Point(RGBColor(red: Constant(0.5), green: Constant(0.5), blue: Constant(0.5))),
+                    end_point: Point(RGBColor(red: Constant(0.5), green: Constant(0.5), blue: Constant(0.5))),
+                    source: "Color",
+                    rescale: Clamp,
+                ),
+            ],
+            sum_type: InvWeighted,
+        ),
+    ],
+)"#;
+
+    fn identity_lut(size: usize) -> Lut {
+        FoldingMachine::from_ron(Path::new("identity.ron"), IDENTITY)
+            .unwrap()
+            .bake_lut(size, &HashMap::new(), &HashMap::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn identity_bakes_identity_cube() {
+        let size = 3;
+        let cube = identity_lut(size).cube_string();
+        let lines: Vec<&str> = cube.lines().collect();
+        assert!(lines.contains(&"LUT_3D_SIZE 3"));
+
+        let entries: Vec<Vec<f32>> = lines
+            .iter()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_digit() || c == '-'))
+            .map(|line| line.split(' ').map(|x| x.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(entries.len(), size * size * size);
+
+        let scale = (size - 1) as f32;
+        for (i, entry) in entries.iter().enumerate() {
+            let expected = [
+                (i % size) as f32 / scale,
+                (i / size % size) as f32 / scale,
+                (i / (size * size)) as f32 / scale,
+            ];
+            for (actual, expected) in entry.iter().zip(expected.iter()) {
+                assert!(
+                    (actual - expected).abs() < 1e-4,
+                    "entry {} is {:?}, expected {:?}",
+                    i,
+                    entry,
+                    expected
+                );
+            }
+        }
+    }
+    #[test]
+    fn identity_hald_matches_shader_lookup() {
+        let size = 4;
+        let lut = identity_lut(size);
+        let hald = lut.hald().unwrap();
+        let width = hald.width();
+        assert_eq!(hald.dimensions(), (8, 8));
+
+        // The shader reads entry i from the pixel below, counted from the bottom left corner.
+        let shader = lut.isf_shader("/*{", "", "", "lut.png").unwrap();
+        assert!(shader.contains(
+            "vec2 pixel = vec2(mod(i, LUT_WIDTH), LUT_WIDTH - 1.0 - floor(i / LUT_WIDTH)) + 0.5;"
+        ));
+
+        let scale = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let i = (r + size * (g + size * b)) as u32;
+                    let (x, y_from_bottom) = (i % width, width - 1 - i / width);
+                    let color = hald.get_pixel(x, width - 1 - y_from_bottom);
+                    let channel = |c: usize| (c as f32 / scale * 255.0).round() as i32;
+                    let expected = [channel(r), channel(g), channel(b)];
+                    for (actual, expected) in [color.0, color.1, color.2].iter().zip(&expected) {
+                        assert!(
+                            (i32::from(*actual) - *expected).abs() <= 1,
+                            "entry ({}, {}, {}) is {:?}",
+                            r,
+                            g,
+                            b,
+                            color
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
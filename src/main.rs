@@ -7,19 +7,35 @@ extern crate serde_json;
 
 extern crate env_logger;
 
+extern crate image;
+
+extern crate naga;
+
 extern crate structopt;
 
 pub mod color;
+pub mod curve;
 pub mod data;
 pub mod dependency;
+pub mod error;
+pub mod evaluation;
 pub mod filters;
 pub mod folding;
 pub mod geometry;
+pub mod glsl;
 pub mod imaging;
+pub mod lut;
+pub mod validation;
+pub mod verify;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use structopt::StructOpt;
 
+use error::ColorfoldError;
+use folding::FoldingMachine;
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "colorfold",
@@ -35,67 +51,340 @@ struct Args {
     /// List of Colorfold Descriptors to convert from JSON form to RON form.
     #[structopt(short="r", long="to-ron", parse(from_os_str))]
     convert_to_ron:  Vec<PathBuf>,
+    /// Filter the images declared by each descriptor on the cpu and save the result as a png next
+    /// to the descriptor.
+    #[structopt(short="e", long="evaluate")]
+    evaluate: bool,
+    /// Image bound to every image source a descriptor does not declare when evaluating.
+    #[structopt(short="i", long="default-image", parse(from_os_str))]
+    default_image: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
-    env_logger::init();
-    let args = Args::from_args();
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Filter images on the cpu with data sources bound from the command line.
+    #[structopt(name = "render")]
+    Render {
+        /// Colorfold Descriptor to render.
+        #[structopt(parse(from_os_str))]
+        descriptor: PathBuf,
+        /// Bind an image source to an image file, written NAME=PATH. Overrides the images declared
+        /// by the descriptor.
+        #[structopt(long = "image")]
+        images: Vec<Binding<PathBuf>>,
+        /// Bind a float source to a value, written NAME=VALUE.
+        #[structopt(long = "float")]
+        floats: Vec<Binding<f32>>,
+        /// Bind a color source to a hex color, written NAME=#RRGGBB.
+        #[structopt(long = "color")]
+        colors: Vec<Binding<color::Color>>,
+        /// File to write the filtered image to.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Bake a descriptor which filters a single image into a 3D lookup table. Outputs ending in
+    /// .png are written as a HaldCLUT, anything else as a .cube file.
+    #[structopt(name = "bake")]
+    Bake {
+        /// Colorfold Descriptor to bake.
+        #[structopt(parse(from_os_str))]
+        descriptor: PathBuf,
+        /// Number of grid points along each axis of the rgb cube, commonly 17, 33 or 65. A
+        /// HaldCLUT needs a square size such as 64.
+        #[structopt(short = "s", long = "size", default_value = "33")]
+        size: usize,
+        /// Also write an ISF shader next to the HaldCLUT which filters by sampling it.
+        #[structopt(long = "isf")]
+        isf: bool,
+        /// Fix a float source to a value, written NAME=VALUE.
+        #[structopt(long = "float")]
+        floats: Vec<Binding<f32>>,
+        /// Fix a color source to a hex color, written NAME=#RRGGBB.
+        #[structopt(long = "color")]
+        colors: Vec<Binding<color::Color>>,
+        /// File to write the lookup table to.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Regenerate the shader for every descriptor under a directory and compare it against the
+    /// stored .fs file of the same name. Also checks that ron and json forms match.
+    #[structopt(name = "verify")]
+    Verify {
+        /// Directory to search for descriptors.
+        #[structopt(parse(from_os_str))]
+        directory: PathBuf,
+        /// Overwrite stored shaders which are missing or out of date.
+        #[structopt(long = "bless")]
+        bless: bool,
+    },
+}
 
-    use folding::FoldingMachine;
-    use std::ffi::OsStr;
-    use std::fs::File;
-    let json_ext = Some(OsStr::new("json"));
+/// A value bound to a named data source on the command line, written NAME=VALUE.
+#[derive(Debug)]
+struct Binding<T> {
+    name: String,
+    value: T,
+}
 
-    for file in args.convert_to_ron {
-        if let Ok(input) = File::open(&file) {
-            if let Ok(folder) = serde_json::from_reader::<_, FoldingMachine>(input) {
-                if let Ok(ron_form) = ron::ser::to_string_pretty(&folder, Default::default()) {
-                    if let Err(e) = std::fs::write(file.with_extension("ron"), &ron_form) {
-                        println!("Failed to write {:#?} to ron!\nError: {}", &file, e);
-                    }
+impl<T> FromStr for Binding<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) if !name.is_empty() => Ok(Binding {
+                name: name.to_owned(),
+                value: value
+                    .parse()
+                    .map_err(|e| format!("Invalid value for {}: {}", name, e))?,
+            }),
+            _ => Err(format!("Expected NAME=VALUE but found {}", s)),
+        }
+    }
+}
+
+/// Command line bindings laid over the defaults declared by a descriptor.
+fn binding_map<T>(defaults: HashMap<String, T>, bindings: Vec<Binding<T>>) -> HashMap<String, T> {
+    let mut map = defaults;
+    map.extend(
+        bindings
+            .into_iter()
+            .map(|Binding { name, value }| (name, value)),
+    );
+    map
+}
+
+/// Describe the unbound sources of a descriptor, or None if everything is bound.
+fn unbound_error(descriptor: &Path, unbound: Vec<data::DataSource>) -> Option<ColorfoldError> {
+    if unbound.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = unbound
+        .iter()
+        .map(|source| {
+            let hint = match source.kind {
+                data::DataSourceKind::Image | data::DataSourceKind::Stage => {
+                    "bind it on the command line or declare it in images"
                 }
-            }
+                data::DataSourceKind::Color | data::DataSourceKind::Float => {
+                    "bind it on the command line or declare a default in inputs"
+                }
+            };
+            format!("    {} {}: {}", source.kind, source.name, hint)
+        })
+        .collect();
+    Some(ColorfoldError::semantic(format!(
+        "{:#?} requires sources which are not bound:\n{}",
+        descriptor,
+        lines.join("\n")
+    )))
+}
+
+/// Verify a directory of descriptors, failing if any of them do not match their stored shaders.
+fn verify(directory: &Path, bless: bool) -> error::Result<()> {
+    let report = verify::verify_directory(directory, bless)?;
+    println!(
+        "{} passed, {} failed, {} blessed",
+        report.passed, report.failed, report.blessed
+    );
+    if report.failed > 0 {
+        return Err(ColorfoldError::Failed(report.failed));
+    }
+    Ok(())
+}
+
+/// Render a descriptor on the cpu. Command line bindings take priority over the images and input
+/// defaults declared by the descriptor and every source the descriptor requires must end up bound.
+fn render(
+    descriptor: &Path,
+    image_bindings: Vec<Binding<PathBuf>>,
+    float_bindings: Vec<Binding<f32>>,
+    color_bindings: Vec<Binding<color::Color>>,
+    output: &Path,
+) -> error::Result<()> {
+    let folder = FoldingMachine::load(descriptor)?;
+
+    let mut images = folder.images();
+    for Binding { name, value } in image_bindings {
+        let location = value.to_string_lossy().into_owned();
+        images.insert(name.clone(), imaging::Image::new(name, location));
+    }
+    let floats = binding_map(folder.default_floats(), float_bindings);
+    let colors = binding_map(folder.default_colors(), color_bindings);
+
+    let unbound = folder.unbound_sources(&images, &colors, &floats)?;
+    if let Some(e) = unbound_error(descriptor, unbound) {
+        return Err(e);
+    }
+
+    folder
+        .evaluate(&mut images, &colors, &floats)?
+        .save(output)
+}
+
+/// Bake a descriptor into a lookup table. The image being filtered is the only source which may
+/// be left unbound, every float and color must be fixed on the command line or have a default
+/// declared by the descriptor.
+fn bake(
+    descriptor: &Path,
+    size: usize,
+    isf: bool,
+    float_bindings: Vec<Binding<f32>>,
+    color_bindings: Vec<Binding<color::Color>>,
+    output: &Path,
+) -> error::Result<()> {
+    let folder = FoldingMachine::load(descriptor)?;
+    let floats = binding_map(folder.default_floats(), float_bindings);
+    let colors = binding_map(folder.default_colors(), color_bindings);
+
+    let source = folder.lut_source()?;
+    let mut images = HashMap::new();
+    images.insert(source.clone(), imaging::Image::new(source, String::new()));
+    let unbound = folder.unbound_sources(&images, &colors, &floats)?;
+    if let Some(e) = unbound_error(descriptor, unbound) {
+        return Err(e);
+    }
+
+    let hald = output.extension() == Some(std::ffi::OsStr::new("png"));
+    if isf && !hald {
+        return Err(ColorfoldError::semantic(
+            "--isf needs a HaldCLUT output ending in .png",
+        ));
+    }
+
+    if isf {
+        let shader = folder.lut_shader(size, &colors, &floats, output)?;
+        let shader_path = output.with_extension("fs");
+        std::fs::write(&shader_path, shader).map_err(|e| ColorfoldError::io(&shader_path, e))
+    } else {
+        let lut = folder.bake_lut(size, &colors, &floats)?;
+        if hald {
+            lut.save_hald(output)
+        } else {
+            lut.save_cube(output)
         }
     }
+}
 
-    for file in args.convert_to_json {
-        if let Ok(input) = File::open(&file) {
-            if let Ok(folder) = ron::de::from_reader::<_, FoldingMachine>(input) {
-                if let Ok(output) = File::create(file.with_extension("json")) {
-                    if let Err(e) = serde_json::to_writer_pretty(output, &folder) {
-                        println!("Failed to write {:#?} to json!\nError: {}", &file, e);
-                    }
+/// Rewrite a json descriptor in ron form next to it.
+fn convert_to_ron(file: &Path) -> error::Result<()> {
+    let input = std::fs::read_to_string(file).map_err(|e| ColorfoldError::io(file, e))?;
+    let folder = FoldingMachine::from_json(file, &input)?;
+    let ron_form = ron::ser::to_string_pretty(&folder, Default::default()).map_err(|e| {
+        ColorfoldError::semantic(format!("Failed to write {:#?} to ron: {}", file, e))
+    })?;
+    let output = file.with_extension("ron");
+    std::fs::write(&output, &ron_form).map_err(|e| ColorfoldError::io(&output, e))
+}
+
+/// Rewrite a ron descriptor in json form next to it.
+fn convert_to_json(file: &Path) -> error::Result<()> {
+    let input = std::fs::read_to_string(file).map_err(|e| ColorfoldError::io(file, e))?;
+    let folder = FoldingMachine::from_ron(file, &input)?;
+    let json_form = serde_json::to_string_pretty(&folder).map_err(|e| {
+        ColorfoldError::semantic(format!("Failed to write {:#?} to json: {}", file, e))
+    })?;
+    let output = file.with_extension("json");
+    std::fs::write(&output, &json_form).map_err(|e| ColorfoldError::io(&output, e))
+}
+
+/// Generate the shader for a descriptor and save it next to the descriptor, optionally
+/// evaluating it on the cpu as well.
+fn generate(file: &Path, evaluate: bool, default_image: Option<&imaging::Image>) -> error::Result<()> {
+    let mut folder = FoldingMachine::load(file)?;
+    folder.vdmx_shader(true)?;
+    if evaluate {
+        let mut images = folder.images();
+        if let Some(image) = default_image {
+            for source in folder.external_sources()? {
+                if source.kind == data::DataSourceKind::Image {
+                    images.entry(source.name).or_insert_with(|| image.clone());
                 }
             }
         }
+        let colors = folder.default_colors();
+        let floats = folder.default_floats();
+        folder
+            .evaluate(&mut images, &colors, &floats)?
+            .save(&file.with_extension("png"))?;
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> error::Result<()> {
+    if let Some(command) = args.command {
+        return match command {
+            Command::Render {
+                descriptor,
+                images,
+                floats,
+                colors,
+                output,
+            } => render(&descriptor, images, floats, colors, &output),
+            Command::Bake {
+                descriptor,
+                size,
+                isf,
+                floats,
+                colors,
+                output,
+            } => bake(&descriptor, size, isf, floats, colors, &output),
+            Command::Verify { directory, bless } => verify(&directory, bless),
+        };
+    }
+
+    let default_image = match args.default_image {
+        Some(ref path) => {
+            let mut image = imaging::Image::new(
+                String::from("default"),
+                path.to_string_lossy().into_owned(),
+            );
+            image.load()?;
+            Some(image)
+        }
+        None => None,
+    };
+
+    // Every file is attempted even after a failure so that all errors are reported at once.
+    let mut failed = 0;
+    let mut report = |result: error::Result<()>| {
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            failed += 1;
+        }
+    };
+
+    for file in args.convert_to_ron {
+        report(convert_to_ron(&file));
+    }
+
+    for file in args.convert_to_json {
+        report(convert_to_json(&file));
     }
 
-    // Get command line arguments (excluding the program name)
     for file in args.descriptors {
         println!("Running folding machine at {:#?}", &file);
-        if let Ok(input) = File::open(&file) {
-            let folder: FoldingMachine = if file.extension() == json_ext {
-                match serde_json::from_reader(input) {
-                    Ok(x) => x,
-                    Err(e) => {
-                        println!("Error: Malformed json input {:#?}.\nError: {}", &file, e);
-                        continue;
-                    }
-                }
-            } else {
-                match ron::de::from_reader(input) {
-                    Ok(x) => x,
-                    Err(e) => {
-                        println!("Error: Malformed ron input {:#?}.\nError: {}", &file, e);
-                        continue;
-                    }
-                }
-            };
-            if let Err(e) = folder.with_location(file.clone()).vdmx_shader(0usize, true) {
-                println!("Failed to save shader {:#?} due to error {}", &file, e);
-            }
-        } else {
-            println!("Error: Could not open file {:#?} for read!", &file);
-        }
+        report(generate(&file, args.evaluate, default_image.as_ref()));
+    }
+
+    if failed > 0 {
+        return Err(ColorfoldError::Failed(failed));
+    }
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::from_args();
+
+    if let Err(e) = run(args) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
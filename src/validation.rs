@@ -0,0 +1,166 @@
+//! # Validation
+//! Semantic checks on descriptors which parse but would generate broken shaders. Every node of a
+//! folding machine reports its problems into a Validation along with the path to the node, such
+//! as `stages[0].operations[1].end_point.start`, so all of them can be reported together.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use data::{DataSource, DataSourceKind};
+use glsl::{library_symbols, mangle, FunctionNames};
+
+/// A problem with a single node of a descriptor.
+pub struct ValidationError {
+    /// Path to the node in the descriptor.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Validation collects the problems found while walking a descriptor.
+#[derive(Default)]
+pub struct Validation {
+    pub errors: Vec<ValidationError>,
+    /// Path of the first stage declaring each name.
+    stage_names: HashMap<String, String>,
+    /// What declares each identifier of the generated shader, and where it was first seen.
+    identifiers: HashMap<String, (String, String)>,
+    /// Compute function names, handed out the same way the shader generator does.
+    functions: FunctionNames,
+}
+
+impl Validation {
+    pub fn new() -> Self {
+        let mut validation = Self::default();
+        for symbol in library_symbols() {
+            let owner = String::from("the shader library");
+            let first = owner.clone();
+            validation.identifiers.insert(symbol, (owner, first));
+        }
+        validation
+    }
+
+    pub fn error<S: Into<String>>(&mut self, path: &str, message: S) {
+        self.errors.push(ValidationError {
+            path: path.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Check that no earlier stage shares a name with this one, as stages are referenced by name.
+    pub fn check_stage_name(&mut self, path: &str, name: &str) {
+        match self.stage_names.get(name) {
+            Some(first) => {
+                let message = format!("Stage name {:?} is already used by {}", name, first);
+                self.error(path, message);
+            }
+            None => {
+                self.stage_names.insert(name.to_owned(), path.to_owned());
+            }
+        }
+    }
+
+    /// Check the identifiers the shader declares for a data source against every other
+    /// identifier in the shader.
+    pub fn check_source(&mut self, path: &str, source: &DataSource) {
+        let name = mangle(&source.name);
+        let identifiers = match source.kind {
+            DataSourceKind::Color => vec![
+                format!("col_{}", name),
+                format!("col_{}__rgb", name),
+                format!("col_{}__hsv", name),
+                format!("col_{}__a", name),
+                format!("col_{}__rgba", name),
+            ],
+            DataSourceKind::Float => vec![format!("float_{}", name)],
+            DataSourceKind::Image | DataSourceKind::Stage => vec![
+                format!("img_{}", name),
+                format!("img_{}__rgb", name),
+                format!("img_{}__hsv", name),
+                format!("img_{}__a", name),
+                format!("{}__img", name),
+            ],
+        };
+        let owner = format!("the {} source {:?}", source.kind, source.name);
+        self.claim(path, owner, identifiers);
+    }
+
+    /// Check the function the shader declares for a compute object against every other
+    /// identifier in the shader.
+    pub fn check_compute_name(&mut self, path: &str, name: &str) {
+        let function = self.functions.allocate(name);
+        self.claim(path, format!("the compute {:?}", name), vec![function]);
+    }
+
+    fn claim(&mut self, path: &str, owner: String, identifiers: Vec<String>) {
+        for identifier in identifiers {
+            match self.identifiers.get(&identifier) {
+                Some((first, _)) if *first == owner => {}
+                Some((_, first)) => {
+                    let message = format!(
+                        "The identifier {} is declared for both {} and {}",
+                        identifier, owner, first
+                    );
+                    self.error(path, message);
+                }
+                None => {
+                    let first = format!("{} at {}", owner, path);
+                    self.identifiers.insert(identifier, (owner.clone(), first));
+                }
+            }
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Vec<ValidationError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, kind: DataSourceKind) -> DataSource {
+        DataSource {
+            name: name.to_owned(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn repeated_names_get_their_own_identifiers() {
+        let mut validation = Validation::new();
+        validation.check_compute_name("stages[0]", "main");
+        validation.check_compute_name("stages[0].operations[0]", "main");
+        validation.check_source("stages[0]", &source("x", DataSourceKind::Image));
+        validation.check_source("stages[0]", &source("x_rgb", DataSourceKind::Image));
+        validation.check_source("stages[1]", &source("x", DataSourceKind::Image));
+        validation.check_source("stages[1]", &source("x", DataSourceKind::Color));
+        validation.check_source("stages[1]", &source("x", DataSourceKind::Float));
+        validation.check_source("stages[1]", &source("rgb2hsv", DataSourceKind::Float));
+        assert!(validation.errors.is_empty());
+    }
+
+    #[test]
+    fn reports_identifiers_declared_twice() {
+        let mut validation = Validation::new();
+        validation.check_source("stages[0]", &source("Out", DataSourceKind::Stage));
+        validation.check_source("stages[1]", &source("Out", DataSourceKind::Image));
+        let messages: Vec<String> = validation.errors.iter().map(|x| x.to_string()).collect();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(
+            messages[0],
+            "stages[1]: The identifier img_Out is declared for both the image source \"Out\" \
+             and the stage source \"Out\" at stages[0]"
+        );
+    }
+}
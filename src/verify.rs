@@ -0,0 +1,170 @@
+//! # Verify
+//! Golden file checks for directories of descriptors. Every descriptor is regenerated and
+//! compared against the shader stored next to it, the regenerated shader must be valid GLSL, and
+//! descriptors stored in both ron and json form must describe the same machine. Descriptors with
+//! an `.err` file next to them must instead be rejected with the error stored in it.
+
+use error::{ColorfoldError, Result};
+use folding::FoldingMachine;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The ron and json forms of a single descriptor, either of which may be missing.
+#[derive(Default)]
+struct DescriptorFiles {
+    ron: Option<PathBuf>,
+    json: Option<PathBuf>,
+}
+
+/// Totals from a verification run.
+#[derive(Default)]
+pub struct VerifyReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub blessed: usize,
+}
+
+/// Verify every descriptor in directory and all of its subdirectories. With bless set, stored
+/// shaders which are missing or out of date are rewritten instead of failing.
+pub fn verify_directory(directory: &Path, bless: bool) -> Result<VerifyReport> {
+    let mut descriptors = BTreeMap::new();
+    collect_descriptors(directory, &mut descriptors)?;
+
+    let mut report = VerifyReport::default();
+    for (stem, files) in descriptors {
+        match verify_descriptor(&stem, &files, bless) {
+            Ok(Some(blessed)) => {
+                println!("blessed {}", blessed.display());
+                report.blessed += 1;
+            }
+            Ok(None) => {
+                println!("ok      {}", stem.display());
+                report.passed += 1;
+            }
+            Err(e) => {
+                eprintln!("FAILED  {}\n{}", stem.display(), e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn collect_descriptors(
+    directory: &Path,
+    descriptors: &mut BTreeMap<PathBuf, DescriptorFiles>,
+) -> Result<()> {
+    let entries = std::fs::read_dir(directory).map_err(|e| ColorfoldError::io(directory, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| ColorfoldError::io(directory, e))?.path();
+        if path.is_dir() {
+            collect_descriptors(&path, descriptors)?;
+            continue;
+        }
+        let stem = path.with_extension("");
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("ron") => descriptors.entry(stem).or_default().ron = Some(path.clone()),
+            Some("json") => descriptors.entry(stem).or_default().json = Some(path.clone()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check a single descriptor, returning the stored file which was blessed, if any.
+fn verify_descriptor(
+    stem: &Path,
+    files: &DescriptorFiles,
+    bless: bool,
+) -> std::result::Result<Option<PathBuf>, String> {
+    let load = |path: &Path| FoldingMachine::load(path).map_err(|e| format!("    {}", e));
+    let ron = match files.ron {
+        Some(ref path) => Some(load(path)?),
+        None => None,
+    };
+    let json = match files.json {
+        Some(ref path) => Some(load(path)?),
+        None => None,
+    };
+
+    if let (Some(ron), Some(json)) = (&ron, &json) {
+        if machine_value(ron)? != machine_value(json)? {
+            return Err(String::from(
+                "    The ron and json forms describe different folding machines",
+            ));
+        }
+    }
+
+    let mut folder = match ron.or(json) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let generated = folder.vdmx_shader(false);
+
+    let error_path = stem.with_extension("err");
+    if error_path.exists() {
+        let error = match generated {
+            Ok(_) => {
+                return Err(format!(
+                    "    Expected the descriptor to be rejected as in {}",
+                    error_path.display()
+                ))
+            }
+            Err(e) => format!("{}\n", e),
+        };
+        return check_stored(&error_path, &error, bless);
+    }
+
+    let shader = generated.map_err(|e| format!("    {}", e))?;
+    check_stored(&stem.with_extension("fs"), &shader, bless)
+}
+
+/// Compare generated output against the file it is stored in, rewriting the file with bless set.
+fn check_stored(
+    stored_path: &Path,
+    generated: &str,
+    bless: bool,
+) -> std::result::Result<Option<PathBuf>, String> {
+    let stored = std::fs::read_to_string(stored_path).ok();
+    if stored.as_deref() == Some(generated) {
+        return Ok(None);
+    }
+    if bless {
+        std::fs::write(stored_path, generated)
+            .map_err(|e| format!("    {}", ColorfoldError::io(stored_path, e)))?;
+        return Ok(Some(stored_path.to_owned()));
+    }
+    match stored {
+        Some(stored) => Err(describe_difference(&stored, generated)),
+        None => Err(format!("    Nothing stored at {}", stored_path.display())),
+    }
+}
+
+/// The machine as a json value, ignoring where it was loaded from.
+fn machine_value(folder: &FoldingMachine) -> std::result::Result<serde_json::Value, String> {
+    let mut value = serde_json::to_value(folder).map_err(|e| format!("    {}", e))?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("location");
+    }
+    Ok(value)
+}
+
+/// Point out the first line where the regenerated output differs from the stored one.
+fn describe_difference(stored: &str, generated: &str) -> String {
+    let mut stored_lines = stored.lines();
+    let mut generated_lines = generated.lines();
+    let mut line = 1;
+    loop {
+        match (stored_lines.next(), generated_lines.next()) {
+            (Some(a), Some(b)) if a == b => line += 1,
+            (a, b) => {
+                return format!(
+                    "    Output differs from the stored file at line {}\n    stored:    {}\n    generated: {}",
+                    line,
+                    a.unwrap_or("<end of file>"),
+                    b.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+}
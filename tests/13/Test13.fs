@@ -0,0 +1,553 @@
+/*{
+    "DESCRIPTION": "An autogenerated shader by colorfold",
+    "CREDIT": "generated by Colorfold",
+    "ISFVSN": "2.0",
+    "CATEGORIES": [
+    "Generator"
+    ],
+    "INPUTS": [
+
+            {
+                "NAME" : "Color__img",
+                "LABEL" : "Color",
+                "TYPE" : "image"
+            }]}*/
+// Color conversion code from
+// http://lolengine.net/blog/2013/07/27/rgb-to-hsv-in-glsl
+
+vec3 rgb2hsv(vec3 c)
+{
+    vec4 K = vec4(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+    vec4 p = c.g < c.b ? vec4(c.bg, K.wz) : vec4(c.gb, K.xy);
+    vec4 q = c.r < p.x ? vec4(p.xyw, c.r) : vec4(c.r, p.yzx);
+
+    float d = q.x - min(q.w, q.y);
+    float e = 1.0e-10;
+    return vec3(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
+
+vec3 hsv2rgb(vec3 c)
+{
+    vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    vec3 p = abs(fract(c.xxx + K.xyz) * 6.0 - K.www);
+    return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
+}
+
+// Now for my code
+
+// ====================================================================
+// == Conversions between the hsv cube and hsv in spherical notation ==
+// ====================================================================
+
+#define M_PI 3.1415926535897932384626433832795
+
+vec4 hsv2half_spherical(vec3 color)
+{
+  float sat_angle = color.y * M_PI / 2.0;
+  float hue_angle = color.x * 2.0 * M_PI;
+  vec3 hue_sat = vec3(sin(sat_angle) * vec2(cos(hue_angle), sin(hue_angle)), cos(sat_angle));
+  return vec4(color.z * hue_sat, 0.0);
+}
+
+vec3 half_spherical2hsv(vec3 color)
+{
+  float hue_angle = atan(color.y, color.x);
+  float sat_angle = atan(length(color.xy), abs(color.z));
+
+  if (hue_angle < 0.0)
+  {
+    hue_angle += 2.0 * M_PI;
+  }
+
+  return vec3(hue_angle / (2.0 * M_PI), sat_angle * 2.0 / M_PI, length(color));
+}
+
+// ====================================================================
+// == Rotation Calculation Routines                                  ==
+// ====================================================================
+
+const float Epsilon = 0.0000001;
+
+/**
+ * Get quaternion which rotates a given angle about a given axis
+ */
+vec4 get_axis_angle_quat(vec3 axis, float angle)
+{
+  return vec4(sin(angle / 2.0) * axis, cos(angle / 2.0));
+}
+
+/**
+ * Calculate the quaternion which rotates start to end
+ */
+vec4 get_rotation_quat(vec3 start, vec3 end)
+{
+  if (length(start) <= Epsilon || length(end) <= Epsilon)
+    return vec4(0, 0, 0, 1);
+
+  vec3 axis = cross(start, end);
+  if (dot(axis, axis) < Epsilon)
+  {
+    axis = cross(start, vec3(0, 1, 0));
+    if (dot(axis, axis) < Epsilon)
+    {
+      axis = cross(start, vec3(1, 0, 0));
+    }
+  }
+  axis /= length(axis);
+
+  float angle = acos(dot(start, end) / (length(start) * length(end)));
+  return vec4(sin(angle / 2.0) * axis, cos(angle / 2.0));
+}
+
+/**
+ * Apply a quaternion rotation to a position vector.
+ */
+vec3 rotate_by_quat(vec3 position, vec4 quaternion)
+{
+  vec4 q = quaternion;
+  vec3 p = position;
+  return p + 2.0 * cross(q.xyz, cross(q.xyz, p) + q.w * p);
+}
+
+/**
+ * Interpolate using rotations between two vectors checking for null values.
+ * Assuming non-null input vectors (w >= -0.5) will always return non-null output
+ */
+vec4 lin_interp(vec4 start_4, vec4 end_4, float percent)
+{
+  if (start_4.w < -0.5 || end_4.w < -0.5)
+  {
+    return vec4(0, 0, 0, -1);
+  }
+  return mix(start_4, end_4, percent);
+}
+
+/**
+ * This is the same as above but returns null if percent is not in [0, 1]
+ */
+vec4 lin_interp_bounded(vec4 start_4, vec4 end_4, float percent)
+{
+  if (percent < 0.0 || percent > 1.0)
+  {
+    return vec4(0, 0, 0, -1);
+  }
+  return mix(start_4, end_4, percent);
+}
+
+/**
+ * Interpolate using rotations between two vectors checking for null values.
+ * Assuming non-null input vectors (w >= -0.5) will always return non-null output
+ */
+vec4 rot_interp(vec4 start_4, vec4 end_4, float percent)
+{
+  if (start_4.w < -0.5 || end_4.w < -0.5)
+  {
+    return vec4(0, 0, 0, -1);
+  }
+  vec3 start = start_4.xyz;
+  vec3 end   = end_4.xyz;
+
+  if (length(start) <= Epsilon)
+    return vec4(end * percent, 0);
+  if (length(end) <= Epsilon)
+    return vec4(start * (1.0 - percent), 0);
+
+  vec3 axis = cross(start, end);
+  if (dot(axis, axis) < Epsilon)
+  {
+    axis = cross(start, vec3(0, 1, 0));
+    if (dot(axis, axis) < Epsilon)
+    {
+      axis = cross(start, vec3(1, 0, 0));
+    }
+  }
+  axis /= length(axis);
+  float angle = acos(dot(start, end) / (length(start) * length(end)));
+
+  vec4 rotation_quat = get_axis_angle_quat(axis, angle * percent);
+  float start_length = length(start);
+  float new_length    = start_length + (length(end) - start_length) * percent;
+
+  return vec4(rotate_by_quat(start * new_length / start_length, rotation_quat), 0);
+}
+
+/**
+ * This is the same as above but returns null if percent is not in [0, 1]
+ */
+vec4 rot_interp_bounded(vec4 start, vec4 end, float percent)
+{
+  if (percent < 0.0 || percent > 1.0)
+  {
+    return vec4(0, 0, 0, -1);
+  }
+  return rot_interp(start, end, percent);
+}
+
+// ====================================================================
+// == Palette Transformation Routines                                ==
+// ====================================================================
+
+// === Rotations
+// ===== All functions return (rotated vector, weight)
+// ======= weight = -1 implies that this rotation should not be counted
+// ======= as part of the sum.
+
+// /**
+//  * Rotate the input vector by the given rotation
+//  */
+// vec4 point_point(vec3 in_vec, vec3 start, vec4 rotation)
+// {
+//   vec3 dist_vec = start - in_vec;
+//   return vec4(rotate_by_quat(in_vec, rotation), dot(dist_vec, dist_vec));
+// }
+
+/**
+ * Rotate the input vector by the rotation between start and end. Returns null
+ * if given null.
+ */
+vec4 point_point(vec4 in_vec, vec4 start, vec4 end)
+{
+  if (in_vec.w < -0.5 || start.w < -0.5 || end.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec4 rotation = get_rotation_quat(start.xyz, end.xyz);
+  vec3 dist_vec = start.xyz - in_vec.xyz;
+  return vec4(rotate_by_quat(in_vec.xyz, rotation), dot(dist_vec, dist_vec));
+}
+
+/**
+ * Rotate the input vector about center by the rotation between start and end
+ * as seen from center. Returns null if given null.
+ */
+vec4 point_point_centered(vec4 in_vec, vec4 start, vec4 end, vec4 center)
+{
+  if (in_vec.w < -0.5 || start.w < -0.5 || end.w < -0.5 || center.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec4 rotation = get_rotation_quat(start.xyz - center.xyz, end.xyz - center.xyz);
+  vec3 dist_vec = start.xyz - in_vec.xyz;
+  return vec4(center.xyz + rotate_by_quat(in_vec.xyz - center.xyz, rotation), dot(dist_vec, dist_vec));
+}
+
+/**
+ * Shift the input vector by the vector from start to end. Returns null if
+ * given null.
+ */
+vec4 point_translate(vec4 in_vec, vec4 start, vec4 end)
+{
+  if (in_vec.w < -0.5 || start.w < -0.5 || end.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec3 dist_vec = start.xyz - in_vec.xyz;
+  return vec4(in_vec.xyz + end.xyz - start.xyz, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Pull the input vector towards center, or push it away for a negative
+ * strength, by strength times a falloff which is 1 at center and 0 beyond
+ * radius. The falloff is:
+ *   0: linear
+ *   1: smooth
+ *   2: constant
+ * Returns null if given null.
+ */
+vec4 density_shift(vec4 in_vec, vec4 center, float strength, float radius, int falloff)
+{
+  if (in_vec.w < -0.5 || center.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec3 dist_vec = center.xyz - in_vec.xyz;
+  float dist = length(dist_vec);
+  float amount = 0.0;
+  if (radius > Epsilon && dist < radius)
+  {
+    if (falloff == 0)
+      amount = 1.0 - dist / radius;
+    else if (falloff == 1)
+      amount = 1.0 - smoothstep(0.0, radius, dist);
+    else
+      amount = 1.0;
+  }
+  vec3 shifted = in_vec.xyz + strength * amount * dist_vec;
+  if (dot(shifted, shifted) > 1.0)
+  {
+    shifted /= length(shifted);
+  }
+  return vec4(shifted, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Reflect the input vector across the plane through point with the given
+ * normal. The mode is:
+ *   0: fold, only reflecting vectors on the side the normal points to
+ *   1: mirror, reflecting every vector
+ * Returns null if given null or a zero normal.
+ */
+vec4 point_reflect(vec4 in_vec, vec4 point, vec4 normal, int mode)
+{
+  if (in_vec.w < -0.5 || point.w < -0.5 || normal.w < -0.5 || length(normal.xyz) < Epsilon)
+    return vec4(0, 0, 0, -1);
+
+  vec3 unit_normal = normalize(normal.xyz);
+  vec3 dist_vec = point.xyz - in_vec.xyz;
+  float side = dot(in_vec.xyz - point.xyz, unit_normal);
+  vec3 reflected = in_vec.xyz;
+  if (mode != 0 || side > 0.0)
+  {
+    reflected -= 2.0 * side * unit_normal;
+  }
+  if (dot(reflected, reflected) > 1.0)
+  {
+    reflected /= length(reflected);
+  }
+  return vec4(reflected, dot(dist_vec, dist_vec));
+}
+
+/**
+ * Rotate the input vector about the grey axis by angle turns, shifting its
+ * hue by angle. Returns null if given null.
+ */
+vec4 point_twist(vec4 in_vec, float angle)
+{
+  if (in_vec.w < -0.5)
+    return vec4(0, 0, 0, -1);
+
+  vec4 rotation = get_axis_angle_quat(vec3(0, 0, 1), angle * 2.0 * M_PI);
+  return vec4(rotate_by_quat(in_vec.xyz, rotation), 0.0);
+}
+
+// === Scaling
+// ===== All functions return (scaled vector with norm <= 1, weight)
+
+/**
+ * Clamps vectors with too much length and does nothing else.
+ */
+vec4 Clamp(vec4 in_vec, float start, float end)
+{
+  vec3 position = in_vec.xyz;
+  if (dot(position, position) > 1.0)
+  {
+    position /= length(position);
+  }
+  return vec4(position, in_vec.w);
+}
+
+/**
+ * Scale the input vector by the given ratio.
+ */
+vec4 RatioClamp(vec4 in_vec, float start, float end)
+{
+  if (start < Epsilon)
+  {
+    return vec4(0, 0, 0, in_vec.w);
+  }
+  else
+  {
+    float ratio = end / start;
+    vec3 scaled = in_vec.xyz * ratio;
+    if (dot(scaled, scaled) > 1.0)
+    {
+      scaled /= length(scaled);
+    }
+    return vec4(scaled, in_vec.w);
+  }
+}
+
+/**
+ * RatioClamp for start points close to black, where the ratio between the
+ * lengths is unstable and a black start point leaves a hole. As the start
+ * length falls below knee the result blends smoothly towards a dark length
+ * which depends on dark:
+ *   0: keep the length of in_vec
+ *   1: interpolate the length through (start, end) and (1, 1)
+ *   2: raise the length to at least floor_length
+ */
+vec4 RatioBlend(vec4 in_vec, float start, float end, float knee, int dark, float floor_length)
+{
+  vec4 ratio = RatioClamp(in_vec, max(start, Epsilon), end);
+  float in_len = length(in_vec.xyz);
+  float dark_len;
+  if (dark == 0)
+  {
+    dark_len = in_len;
+  }
+  else if (dark == 1)
+  {
+    dark_len = mix(end, 1.0, (in_len - start) / max(1.0 - start, Epsilon));
+  }
+  else
+  {
+    dark_len = max(in_len, floor_length);
+  }
+  dark_len = clamp(dark_len, 0.0, 1.0);
+
+  // Black has no direction of its own, so it is lifted along the grey axis.
+  vec3 direction = in_len < Epsilon ? vec3(0, 0, 1) : in_vec.xyz / in_len;
+  float weight = knee < Epsilon ? 1.0 : smoothstep(0.0, knee, start);
+  return vec4(mix(direction * dark_len, ratio.xyz, weight), in_vec.w);
+}
+
+/**
+ * Roll lengths above knee off smoothly towards 1 instead of clipping them.
+ * Lengths up to knee are kept and the slope is continuous at knee.
+ */
+float soft_knee(float len, float knee)
+{
+  float headroom = 1.0 - knee;
+  if (len <= knee) return len;
+  if (headroom < Epsilon) return 1.0;
+  float over = len - knee;
+  return knee + over / (1.0 + over / headroom);
+}
+
+/**
+ * Gamut compression of value only. Scales by the ratio between end and start
+ * when ratio is set, then rolls the length off above knee, which keeps hue
+ * and saturation.
+ */
+vec4 SoftValue(vec4 in_vec, float start, float end, float knee, bool ratio)
+{
+  vec3 position = in_vec.xyz;
+  if (ratio)
+  {
+    position *= start < Epsilon ? 0.0 : end / start;
+  }
+  float len = length(position);
+  if (len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+  return vec4(position * soft_knee(len, knee) / len, in_vec.w);
+}
+
+/**
+ * SoftValue which also shrinks the saturation angle by the same proportion
+ * as the length is rolled off, so over-bright colors fade towards white.
+ */
+vec4 SoftSaturation(vec4 in_vec, float start, float end, float knee, bool ratio)
+{
+  vec3 position = in_vec.xyz;
+  if (ratio)
+  {
+    position *= start < Epsilon ? 0.0 : end / start;
+  }
+  float len = length(position);
+  if (len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+  float new_len = soft_knee(len, knee);
+  if (length(position.xy) < Epsilon) return vec4(position * new_len / len, in_vec.w);
+
+  float hue_angle = atan(position.y, position.x);
+  float sat_angle = atan(length(position.xy), abs(position.z)) * new_len / len;
+  float z = position.z < 0.0 ? -cos(sat_angle) : cos(sat_angle);
+  vec3 direction = vec3(sin(sat_angle) * vec2(cos(hue_angle), sin(hue_angle)), z);
+  return vec4(new_len * direction, in_vec.w);
+}
+
+/**
+ * Scale the in_vec by the bezier curve given by the controls
+ *        (0, 0), (start_mid, end_mid), (1, 1).
+ * Note: This scheme will not necessarily map a vector of length
+ * start_mid to a vector of length end_mid.
+ */
+vec4 BezierLoose(vec4 in_vec, float start_mid, float end_mid)
+{
+  float in_len     = length(in_vec.xyz);
+  if (in_len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+
+  float percent    = mix(mix(0.0, start_mid, in_len),
+                         mix(start_mid, 1.0, in_len), in_len);
+  float new_length = mix(mix(0.0, end_mid, percent),
+                         mix(end_mid, 1.0, percent), percent);
+  return vec4(new_length / in_len * in_vec.xyz, in_vec.w);
+}
+
+/**
+ * Scale the in_vec by a monotone curve through (0, 0), (start_mid, end_mid)
+ * and (1, 1), made of two quadratic bezier segments which meet at
+ * (start_mid, end_mid) with a shared tangent. Unlike BezierLoose a vector of
+ * length start_mid is always mapped to a vector of length end_mid.
+ */
+vec4 BezierStrict(vec4 in_vec, float start_mid, float end_mid)
+{
+  float in_len = length(in_vec.xyz);
+  if (in_len < Epsilon) return vec4(0, 0, 0, in_vec.w);
+
+  float s = clamp(start_mid, 0.0, 1.0);
+  float e = clamp(end_mid, 0.0, 1.0);
+  float x = min(in_len, 1.0);
+
+  // The harmonic mean of the slopes either side of the midpoint keeps the
+  // controls of both segments inside their bounds, so the curve is monotone.
+  float denominator = e * (1.0 - s) + s * (1.0 - e);
+  float slope = denominator < Epsilon ? 1.0 : 2.0 * e * (1.0 - e) / denominator;
+
+  // The controls sit halfway along each segment in x, so x is linear in t.
+  float new_length;
+  if (x <= s)
+  {
+    float t = x / s;
+    float control = e - slope * s / 2.0;
+    new_length = mix(mix(0.0, control, t), mix(control, e, t), t);
+  }
+  else if (s > 1.0 - Epsilon)
+  {
+    new_length = 1.0;
+  }
+  else
+  {
+    float t = (x - s) / (1.0 - s);
+    float control = e + slope * (1.0 - s) / 2.0;
+    new_length = mix(mix(e, control, t), mix(control, 1.0, t), t);
+  }
+  return vec4(new_length / in_len * in_vec.xyz, in_vec.w);
+}
+// stages[0]
+vec4 compute_Out(vec3 img_Color__rgb)
+{
+vec3 img_Color__hsv = rgb2hsv(img_Color__rgb);
+vec4 img_Color = hsv2half_spherical(img_Color__hsv);
+  float total_inv_weight = 0.0;
+  vec3 total_inv_weight_vecs = vec3(0);
+  int num_zeros = 0;
+  vec3 total_zeros = vec3(0);
+  vec4 _rot_start_ = vec4(0);
+  vec4 _rot_end_ = vec4(0);
+// stages[0].operations[0]
+_rot_start_ = hsv2half_spherical(rgb2hsv(vec3(1.0, 0.0, 0.0)));
+_rot_end_ = hsv2half_spherical(rgb2hsv(vec3(0.0, 1.0, 0.0)));
+if (_rot_start_.w > -0.5 && _rot_end_.w > -0.5)
+{
+    vec4 Color__rot = RatioClamp(point_point(img_Color, _rot_start_, _rot_end_), length(_rot_start_.xyz), length(_rot_end_.xyz));
+    if (Color__rot.w > -0.5)
+    {
+        if (Color__rot.w < Epsilon)
+        {
+            num_zeros += 1;
+            total_zeros += Color__rot.xyz;
+        }
+        else
+        {
+            total_inv_weight += 1.0 / Color__rot.w;
+            total_inv_weight_vecs += 1.0 / Color__rot.w * Color__rot.xyz;
+        }
+    }
+}
+// stages[0]
+  if (num_zeros > 0)
+  {
+    return vec4(total_zeros / float(num_zeros), 0);
+  }
+  else if (total_inv_weight > Epsilon)
+  {
+      return vec4((1.0 / total_inv_weight) * total_inv_weight_vecs, 0);
+  }
+  else
+  {
+      return vec4(0, 0, 0, -1);
+  }
+}
+void main()
+{
+      vec3 img_Color__rgb = IMG_THIS_PIXEL(Color__img).xyz;
+      vec3 img_Color__hsv = rgb2hsv(img_Color__rgb);
+  // Convert the out_color back into rgb. Maintain alpha.
+  vec3 color_out = vec3(hsv2rgb(half_spherical2hsv(compute_Out(img_Color__rgb).xyz)));
+  gl_FragColor = vec4(color_out, img_Color__hsv.z);
+}